@@ -0,0 +1,337 @@
+pub mod report;
+
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::client::memory::{MemoryClient, MemoryMarket};
+use crate::client::types::{Money, Player, PlayerId};
+use crate::client::{ClientError, MarketApi, MarketSnapshot};
+use crate::game::{self, Action, ActionOutcome, Bookkeeper, Shutdown, Strategy};
+use crate::metrics::Metrics;
+use crate::simulator::customers::CustomerModel;
+
+pub use self::report::{ArticleReport, BacktestReport};
+
+// Replays a recorded market against a strategy. Articles, tags, suppliers and the listings of
+// other players come from the recording, our own player and listings are simulated in a
+// MemoryMarket and customers buy according to the customer model between ticks.
+pub struct Backtest {
+    snapshots: Vec<MarketSnapshot>,
+    customers: CustomerModel,
+    starting_money: Option<Money>,
+    rng: StdRng,
+}
+
+impl Backtest {
+    pub fn new(snapshots: Vec<MarketSnapshot>) -> Self {
+        Self {
+            snapshots,
+            customers: CustomerModel::default(),
+            starting_money: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn customers(mut self, customers: CustomerModel) -> Self {
+        self.customers = customers;
+        self
+    }
+
+    // Defaults to the money of the recorded player in the first snapshot
+    pub fn starting_money(mut self, starting_money: Money) -> Self {
+        self.starting_money = Some(starting_money);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub async fn run<S: Strategy + ?Sized>(
+        mut self,
+        strategy: &mut S,
+    ) -> Result<BacktestReport, ClientError> {
+        let first = match self.snapshots.first() {
+            Some(first) => first,
+            None => return Ok(BacktestReport::default()),
+        };
+
+        // We play as the recorded player, so other listings keep their sellers
+        let player_id = first.player.id;
+        let starting_money = self.starting_money.unwrap_or(first.player.money);
+
+        let mut market = MemoryMarket::new(
+            first.articles.clone(),
+            first.tags.clone(),
+            first.suppliers.clone(),
+        );
+        market.players.push(Player {
+            id: player_id,
+            money: starting_money,
+            stock: Vec::new(),
+        });
+        // Our listings must not clash with any recorded one
+        if let Some(highest_listing_id) = self
+            .snapshots
+            .iter()
+            .flat_map(|snapshot| snapshot.listings.iter())
+            .map(|listing| listing.id)
+            .max()
+        {
+            market.reserve_listing_ids(highest_listing_id);
+        }
+
+        let mut client = match MemoryClient::new(market, player_id) {
+            Some(client) => client,
+            None => return Ok(BacktestReport::default()),
+        };
+
+        let mut report = BacktestReport {
+            starting_money,
+            ..BacktestReport::default()
+        };
+        let shutdown = Shutdown::never();
+        // Finds the sales the strategy reacts to, like in a real game
+        let metrics = Metrics::default();
+        let mut bookkeeper = Bookkeeper::new(strategy, &metrics);
+        let mut observer = Observer {
+            strategy: &mut bookkeeper,
+            report: &mut report,
+        };
+
+        observer.on_start(&MarketSnapshot::capture(client.state(), 0, Duration::ZERO));
+
+        for recorded in &self.snapshots {
+            replay(&mut client.market, recorded, player_id);
+            client.fetch_market().await?;
+
+            game::play_tick(
+                &mut client,
+                &mut observer,
+                recorded.tick,
+                recorded.elapsed,
+                &shutdown,
+            )
+            .await?;
+
+            for sale in self.customers.serve(&mut client.market, &mut self.rng) {
+                if sale.seller == player_id {
+                    observer
+                        .report
+                        .record_sale(sale.article_id, sale.count, sale.price_per_unit);
+                }
+            }
+
+            game::watch(
+                &mut client,
+                &mut observer,
+                recorded.tick,
+                recorded.elapsed,
+                None,
+                &shutdown,
+            )
+            .await?;
+
+            observer.report.ticks += 1;
+        }
+
+        client.fetch_market().await?;
+        report.finish(client.state());
+
+        Ok(report)
+    }
+}
+
+// Bring the market to the recorded state, everything of ours stays as simulated
+fn replay(market: &mut MemoryMarket, recorded: &MarketSnapshot, player_id: PlayerId) {
+    market.articles = recorded.articles.clone();
+    market.tags = recorded.tags.clone();
+    market.suppliers = recorded.suppliers.clone();
+
+    market
+        .listings
+        .retain(|listing| listing.player == player_id);
+    market.listings.extend(
+        recorded
+            .listings
+            .iter()
+            .filter(|listing| listing.player != player_id)
+            .cloned(),
+    );
+}
+
+// Passes everything through to the strategy and books the purchases that went through
+struct Observer<'a, S: ?Sized> {
+    strategy: &'a mut S,
+    report: &'a mut BacktestReport,
+}
+
+impl<S: Strategy + ?Sized> Strategy for Observer<'_, S> {
+    fn name(&self) -> &str {
+        self.strategy.name()
+    }
+
+    fn on_start(&mut self, snapshot: &MarketSnapshot) {
+        self.strategy.on_start(snapshot)
+    }
+
+    fn on_tick(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        self.strategy.on_tick(snapshot)
+    }
+
+    fn on_restock(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        self.strategy.on_restock(snapshot)
+    }
+
+    fn on_tick_end(&mut self, snapshot: &MarketSnapshot) {
+        self.strategy.on_tick_end(snapshot)
+    }
+
+    fn on_watch(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        self.strategy.on_watch(snapshot)
+    }
+
+    fn on_outcome(&mut self, action: &Action, outcome: &Result<ActionOutcome, ClientError>) {
+        if let (
+            Action::Buy {
+                article_id,
+                count,
+                price_per_unit,
+                ..
+            },
+            Ok(_),
+        ) = (action, outcome)
+        {
+            self.report
+                .record_purchase(*article_id, *count, *price_per_unit);
+        }
+        self.strategy.on_outcome(action, outcome)
+    }
+
+    fn piggybank(&self) -> Option<Money> {
+        self.strategy.piggybank()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::types::{ArticleId, ListingId, SupplierId};
+    use crate::client::MarketState;
+    use crate::game::testing::{TestMarket, OTHER_PLAYER};
+
+    // Buys 4 units on the first tick and lists 3 of them
+    #[derive(Default)]
+    struct Script {
+        created: Vec<ListingId>,
+    }
+
+    impl Strategy for Script {
+        fn name(&self) -> &str {
+            "script"
+        }
+
+        fn on_tick(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+            if snapshot.tick != 1 {
+                return Vec::new();
+            }
+            vec![Action::Buy {
+                supplier_id: SupplierId(1),
+                article_id: ArticleId(1),
+                count: 4,
+                price_per_unit: Money::from_f64(10.0),
+            }]
+        }
+
+        fn on_restock(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+            if snapshot.tick != 1 {
+                return Vec::new();
+            }
+            vec![Action::CreateListing {
+                article_id: ArticleId(1),
+                count: 3,
+                price_per_unit: Money::from_f64(12.0),
+            }]
+        }
+
+        fn on_outcome(&mut self, _action: &Action, outcome: &Result<ActionOutcome, ClientError>) {
+            if let Ok(ActionOutcome::ListingCreated(listing_id)) = outcome {
+                self.created.push(*listing_id);
+            }
+        }
+    }
+
+    // Three ticks, the supplier price goes up from 10 to 12 on the last one. Another player
+    // has listing 50 all along.
+    fn snapshots() -> Vec<MarketSnapshot> {
+        [10.0, 10.0, 12.0]
+            .iter()
+            .enumerate()
+            .map(|(tick, supplier_price)| {
+                let market = TestMarket::default()
+                    .article(1, &[], *supplier_price)
+                    .listing(50, OTHER_PLAYER, 1, 5, 100.0);
+                let mut state = MarketState::default();
+                market.fetch_into(&mut state);
+                MarketSnapshot::capture(
+                    &state,
+                    tick as u64 + 1,
+                    Duration::from_secs(30 * tick as u64),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn adds_up_the_money() {
+        let mut script = Script::default();
+        let report = Backtest::new(snapshots())
+            .starting_money(Money::from_f64(500.0))
+            .seed(7)
+            .run(&mut script)
+            .await
+            .unwrap();
+
+        assert_eq!(report.ticks, 3);
+        assert_eq!(report.starting_money, Money::from_f64(500.0));
+        let article = &report.articles[&ArticleId(1)];
+        assert_eq!(article.units_bought, 4);
+        assert_eq!(article.cost, Money::from_f64(40.0));
+        assert!(article.units_sold > 0);
+        assert!(article.units_sold <= 3);
+        assert_eq!(article.revenue, Money::from_f64(12.0) * article.units_sold);
+        assert_eq!(article.profit(), article.revenue - article.cost);
+
+        // Everything we didn't sell is left at the average of 10 and 12
+        assert_eq!(
+            report.final_money,
+            report.starting_money - article.cost + article.revenue
+        );
+        assert_eq!(
+            report.inventory_value,
+            Money::from_f64(11.0) * (article.units_bought - article.units_sold)
+        );
+        assert_eq!(
+            report.sell_through(),
+            article.units_sold as f64 / article.units_bought as f64
+        );
+
+        // Our listing never takes the id of a recorded one
+        assert_eq!(script.created.len(), 1);
+        assert!(script.created[0] > ListingId(50));
+    }
+
+    #[tokio::test]
+    async fn reports_nothing_without_snapshots() {
+        let report = Backtest::new(Vec::new())
+            .run(&mut Script::default())
+            .await
+            .unwrap();
+
+        assert_eq!(report.ticks, 0);
+        assert_eq!(report.sell_through(), 0.0);
+    }
+}
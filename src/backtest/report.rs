@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::client::types::{ArticleId, Money};
+use crate::client::MarketState;
+
+#[derive(Debug, Clone, Default)]
+pub struct ArticleReport {
+    pub units_bought: usize,
+    pub cost: Money,
+    pub units_sold: usize,
+    pub revenue: Money,
+}
+
+impl ArticleReport {
+    pub fn profit(&self) -> Money {
+        self.revenue - self.cost
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BacktestReport {
+    pub ticks: usize,
+    pub starting_money: Money,
+    pub final_money: Money,
+    // What's left in stock and in our listings, valued at the average supplier price
+    pub inventory_value: Money,
+    pub articles: BTreeMap<ArticleId, ArticleReport>,
+}
+
+impl BacktestReport {
+    pub(crate) fn record_purchase(
+        &mut self,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) {
+        let article = self.articles.entry(article_id).or_default();
+        article.units_bought += count;
+        article.cost += price_per_unit * count;
+    }
+
+    pub(crate) fn record_sale(
+        &mut self,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) {
+        let article = self.articles.entry(article_id).or_default();
+        article.units_sold += count;
+        article.revenue += price_per_unit * count;
+    }
+
+    pub(crate) fn finish(&mut self, state: &MarketState) {
+        self.final_money = state.player.money;
+
+        let stock = state
+            .player
+            .stock
+            .iter()
+            .map(|stock| (stock.article_id, stock.stock));
+        let listed = state
+            .listings
+            .iter()
+            .filter(|listing| listing.player == state.player.id)
+            .map(|listing| (listing.article, listing.count));
+        self.inventory_value = stock
+            .chain(listed)
+            .map(
+                |(article_id, count)| match state.article_price_history.get(&article_id) {
+                    Some(history) => history.average_price() * count,
+                    None => Money::ZERO,
+                },
+            )
+            .sum();
+    }
+
+    pub fn units_bought(&self) -> usize {
+        self.articles
+            .values()
+            .map(|article| article.units_bought)
+            .sum()
+    }
+
+    pub fn units_sold(&self) -> usize {
+        self.articles
+            .values()
+            .map(|article| article.units_sold)
+            .sum()
+    }
+
+    // Share of the bought units that found a customer
+    pub fn sell_through(&self) -> f64 {
+        match self.units_bought() {
+            0 => 0.0,
+            units_bought => self.units_sold() as f64 / units_bought as f64,
+        }
+    }
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Ticks: {}", self.ticks)?;
+        writeln!(
+            f,
+            "Money: {:.2} (started with {:.2})",
+            self.final_money, self.starting_money
+        )?;
+        writeln!(f, "Inventory value: {:.2}", self.inventory_value)?;
+        writeln!(
+            f,
+            "Sell-through: {:.1}% ({} of {} units)",
+            self.sell_through() * 100.0,
+            self.units_sold(),
+            self.units_bought()
+        )?;
+        writeln!(f, "Article  bought      cost  sold   revenue    profit")?;
+        for (article_id, article) in &self.articles {
+            writeln!(
+                f,
+                "{:>7} {:>7} {:>9.2} {:>5} {:>9.2} {:>9.2}",
+                article_id,
+                article.units_bought,
+                article.cost,
+                article.units_sold,
+                article.revenue,
+                article.profit()
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Instant;
+
+use super::fetch::{FetchReport, MarketPart};
+use super::types::{ArticleId, ListingId, Money, SupplierId};
+use super::{
+    error::ClientError, ratelimit::RateLimitStats, state::MarketState, stats::RequestStats,
+};
+
+// Everything the game needs from a marketplace. Implementations keep their MarketState
+// up to date on every successful fetch, so the game only reads from there.
+pub trait MarketApi: Send {
+    fn state(&self) -> &MarketState;
+    fn state_mut(&mut self) -> &mut MarketState;
+
+    // How often and how long requests had to wait for a client-side rate limiter, per endpoint
+    fn rate_limit_stats(&self) -> HashMap<String, RateLimitStats> {
+        HashMap::new()
+    }
+
+    // Count, errors and latency of the requests sent to the server, per endpoint
+    fn request_stats(&self) -> HashMap<String, RequestStats> {
+        HashMap::new()
+    }
+
+    // The clock the game runs on. Replays use the recorded time instead of the real one.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn fetch_player_self(&mut self) -> impl Future<Output = Result<(), ClientError>> + Send;
+    fn fetch_articles(&mut self) -> impl Future<Output = Result<(), ClientError>> + Send;
+    fn fetch_tags(&mut self) -> impl Future<Output = Result<(), ClientError>> + Send;
+    fn fetch_suppliers(&mut self) -> impl Future<Output = Result<(), ClientError>> + Send;
+    fn fetch_listings(&mut self) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    // The whole market in one go. Parts that fail keep their previous data and are listed in
+    // the report, which also ends up in the state. Only fatal errors fail the fetch.
+    fn fetch_market(&mut self) -> impl Future<Output = Result<FetchReport, ClientError>> + Send {
+        async move {
+            let mut report = FetchReport::start();
+            report.record(MarketPart::Player, self.fetch_player_self().await)?;
+            report.record(MarketPart::Articles, self.fetch_articles().await)?;
+            report.record(MarketPart::Tags, self.fetch_tags().await)?;
+            report.record(MarketPart::Suppliers, self.fetch_suppliers().await)?;
+            report.record(MarketPart::Listings, self.fetch_listings().await)?;
+
+            self.state_mut().last_fetch = report.clone();
+            Ok(report)
+        }
+    }
+
+    fn buy_from_supplier(
+        &mut self,
+        supplier_id: SupplierId,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    fn create_listing(
+        &mut self,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> impl Future<Output = Result<ListingId, ClientError>> + Send;
+
+    fn _delete_listing(
+        &mut self,
+        listing_id: ListingId,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+
+    fn update_listing(
+        &mut self,
+        listing_id: ListingId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> impl Future<Output = Result<(), ClientError>> + Send;
+}
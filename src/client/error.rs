@@ -0,0 +1,122 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+#[derive(Debug)]
+pub enum ClientError {
+    // The request never got an answer (connection refused, timeout, …)
+    Transport {
+        endpoint: String,
+        source: reqwest::Error,
+    },
+    // The server answered, but not with 200 OK
+    Status {
+        endpoint: String,
+        status: StatusCode,
+        body: String,
+    },
+    // The server answered with something that isn't the JSON we expected
+    Decode {
+        endpoint: String,
+        source: serde_json::Error,
+    },
+    // Valid JSON, but it doesn't make sense (e.g. not exactly one player)
+    UnexpectedShape {
+        endpoint: String,
+        reason: String,
+    },
+    // A recorded request that never got an answer, replayed from a tape
+    Replayed {
+        endpoint: String,
+        reason: String,
+    },
+    // The tape doesn't match the requests of this session (or has run out)
+    Tape {
+        endpoint: String,
+        reason: String,
+    },
+}
+
+impl ClientError {
+    pub fn endpoint(&self) -> &str {
+        match self {
+            ClientError::Transport { endpoint, .. }
+            | ClientError::Status { endpoint, .. }
+            | ClientError::Decode { endpoint, .. }
+            | ClientError::UnexpectedShape { endpoint, .. }
+            | ClientError::Replayed { endpoint, .. }
+            | ClientError::Tape { endpoint, .. } => endpoint,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    // Our credentials are wrong, no point in trying again
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(
+            self.status(),
+            Some(StatusCode::UNAUTHORIZED) | Some(StatusCode::FORBIDDEN)
+        )
+    }
+
+    // Nothing we can carry on after: bad credentials or a replay that went off the tape
+    pub fn is_fatal(&self) -> bool {
+        self.is_unauthorized() || matches!(self, ClientError::Tape { .. })
+    }
+
+    // The server understood us, but didn't like what we asked for (e.g. a buy over budget)
+    pub fn is_rejected(&self) -> bool {
+        match self.status() {
+            Some(status) => status.is_client_error() && !self.is_unauthorized(),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Transport { endpoint, source } => {
+                write!(f, "{}: Failed to receive response ({})", endpoint, source)
+            }
+            ClientError::Status {
+                endpoint,
+                status,
+                body,
+            } => {
+                if body.is_empty() {
+                    write!(f, "{}: Unexpected status {}", endpoint, status)
+                } else {
+                    write!(f, "{}: Unexpected status {} ({})", endpoint, status, body)
+                }
+            }
+            ClientError::Decode { endpoint, source } => {
+                write!(f, "{}: Failed to parse type ({})", endpoint, source)
+            }
+            ClientError::UnexpectedShape { endpoint, reason } => {
+                write!(f, "{}: Unexpected response ({})", endpoint, reason)
+            }
+            ClientError::Replayed { endpoint, reason } => {
+                write!(f, "{}: Recorded failure ({})", endpoint, reason)
+            }
+            ClientError::Tape { endpoint, reason } => {
+                write!(f, "{}: Replay failed ({})", endpoint, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Transport { source, .. } => Some(source),
+            ClientError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
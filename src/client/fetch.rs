@@ -0,0 +1,164 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::ClientError;
+
+// The parts of the market we fetch at the start of every tick, one endpoint each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketPart {
+    Player,
+    Articles,
+    Tags,
+    Suppliers,
+    Listings,
+}
+
+impl MarketPart {
+    pub const ALL: [MarketPart; 5] = [
+        MarketPart::Player,
+        MarketPart::Articles,
+        MarketPart::Tags,
+        MarketPart::Suppliers,
+        MarketPart::Listings,
+    ];
+
+    pub fn endpoint(self) -> &'static str {
+        match self {
+            MarketPart::Player => "/player/self",
+            MarketPart::Articles => "/article",
+            MarketPart::Tags => "/tag",
+            MarketPart::Suppliers => "/supplier",
+            MarketPart::Listings => "/listing",
+        }
+    }
+}
+
+impl fmt::Display for MarketPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.endpoint())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedFetch {
+    pub part: MarketPart,
+    pub reason: String,
+}
+
+// How the last fetch of the whole market went. Parts that failed still hold the data of
+// an earlier fetch, strategies can check for that before trusting them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetchReport {
+    // Microseconds since the unix epoch when the requests went out
+    pub captured_at: u64,
+    pub failures: Vec<FailedFetch>,
+}
+
+impl FetchReport {
+    pub fn start() -> Self {
+        Self {
+            captured_at: unix_time_in_us(),
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    pub fn is_stale(&self, part: MarketPart) -> bool {
+        self.failures.iter().any(|failure| failure.part == part)
+    }
+
+    // Nothing new at all, we might as well not have asked
+    pub fn is_total_failure(&self) -> bool {
+        MarketPart::ALL.iter().all(|part| self.is_stale(*part))
+    }
+
+    // Fatal errors fail the whole fetch, anything else is noted down and the part goes stale
+    pub fn record<V>(
+        &mut self,
+        part: MarketPart,
+        result: Result<V, ClientError>,
+    ) -> Result<Option<V>, ClientError> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.is_fatal() => Err(e),
+            Err(e) => {
+                self.failures.push(FailedFetch {
+                    part,
+                    reason: e.to_string(),
+                });
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn unix_time_in_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    fn status(status: StatusCode) -> ClientError {
+        ClientError::Status {
+            endpoint: "/tag".to_string(),
+            status,
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn notes_failed_parts() {
+        let mut report = FetchReport::start();
+
+        assert_eq!(report.record(MarketPart::Tags, Ok(1)).unwrap(), Some(1));
+        assert!(report.is_complete());
+
+        let failed = report.record::<u32>(MarketPart::Tags, Err(status(StatusCode::BAD_GATEWAY)));
+        assert_eq!(failed.unwrap(), None);
+        assert!(!report.is_complete());
+        assert!(report.is_stale(MarketPart::Tags));
+        assert!(!report.is_stale(MarketPart::Player));
+        assert_eq!(report.failures[0].part, MarketPart::Tags);
+    }
+
+    #[test]
+    fn passes_fatal_errors_on() {
+        let mut report = FetchReport::start();
+
+        let failed =
+            report.record::<u32>(MarketPart::Player, Err(status(StatusCode::UNAUTHORIZED)));
+        assert!(failed.unwrap_err().is_fatal());
+        assert!(report.is_complete());
+    }
+
+    #[test]
+    fn fails_totally_only_without_any_part() {
+        let mut report = FetchReport::start();
+        for part in &MarketPart::ALL[1..] {
+            report
+                .record::<u32>(*part, Err(status(StatusCode::INTERNAL_SERVER_ERROR)))
+                .unwrap();
+        }
+        assert!(!report.is_total_failure());
+
+        report
+            .record::<u32>(
+                MarketPart::Player,
+                Err(status(StatusCode::INTERNAL_SERVER_ERROR)),
+            )
+            .unwrap();
+        assert!(report.is_total_failure());
+    }
+}
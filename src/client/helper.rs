@@ -1,62 +1,157 @@
-use super::{types::Listing, Client};
+use super::types::{
+    Article, ArticleId, Listing, ListingId, Money, PlayerId, Stock, SupplierId, Tag, TagId,
+};
+use super::MarketSnapshot;
 
-impl Client {
-    pub fn get_tags_for_article_id(&self, article_id: usize) -> Vec<(usize, Vec<usize>)> {
-        let article_tags = self
-            .articles
-            .iter()
-            .filter(|article| article.id == article_id)
-            .map(|article| article.tags.clone())
-            .next();
-        if article_tags.is_none() {
-            return Vec::new();
-        }
+// What our listings are worth, roughly
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Portfolio {
+    pub items: usize,
+    // Priced down towards the average supplier price
+    pub min_value: Money,
+    // Selling at our listing prices
+    pub max_value: Money,
+}
 
-        let article_tags = article_tags.unwrap();
+impl MarketSnapshot {
+    pub fn article(&self, article_id: ArticleId) -> Option<&Article> {
+        self.index().article(&self.articles, article_id)
+    }
 
-        let mut result = Vec::new();
+    pub fn articles_with_tag(&self, tag_id: TagId) -> impl Iterator<Item = &Article> {
+        self.index().articles_with_tag(&self.articles, tag_id)
+    }
 
-        for article_tag in article_tags {
-            let tag_and_similar_tags = self
-                .tags
-                .iter()
-                .filter(|tag| tag.id == article_tag)
-                .map(|tag| (tag.id, tag.similar_tags.clone()))
-                .next();
-            result.push(match tag_and_similar_tags {
-                Some(tag_and_similar_tags) => tag_and_similar_tags,
-                None => (article_tag, Vec::new()),
-            });
-        }
+    pub fn tag(&self, tag_id: TagId) -> Option<&Tag> {
+        self.index().tag(&self.tags, tag_id)
+    }
 
-        result
+    pub fn supplier_stock(&self, article_id: ArticleId) -> &[(SupplierId, Stock)] {
+        self.index().supplier_stock(article_id)
     }
 
-    // Filter out other players, we don't trust their data anyway
-    // Filter out our own bedazzlement listings
-    pub fn get_own_listings(&self) -> Vec<Listing> {
-        self.listings
+    // Suppliers that have the article, cheapest first. Ties go to the lower supplier id.
+    pub fn suppliers_by_price(&self, article_id: ArticleId) -> Vec<(SupplierId, Stock)> {
+        let mut supplier_stock = self
+            .supplier_stock(article_id)
             .iter()
-            .filter(|listing| {
-                listing.player == self.player.id
-                    && !self.bedazzlement_listings.contains(&listing.id)
+            .filter(|(_, stock)| stock.stock > 0)
+            .cloned()
+            .collect::<Vec<_>>();
+        supplier_stock.sort_by_key(|(supplier_id, stock)| (stock.price, *supplier_id));
+        supplier_stock
+    }
+
+    pub fn listing(&self, listing_id: ListingId) -> Option<&Listing> {
+        self.index().listing(&self.listings, listing_id)
+    }
+
+    pub fn listings_for_article(&self, article_id: ArticleId) -> impl Iterator<Item = &Listing> {
+        self.index()
+            .listings_for_article(&self.listings, article_id)
+    }
+
+    pub fn listings_of_player(&self, player_id: PlayerId) -> impl Iterator<Item = &Listing> {
+        self.index().listings_of_player(&self.listings, player_id)
+    }
+
+    pub fn get_tags_for_article_id(&self, article_id: ArticleId) -> Vec<(TagId, Vec<TagId>)> {
+        let article = match self.article(article_id) {
+            Some(article) => article,
+            None => return Vec::new(),
+        };
+
+        article
+            .tags
+            .iter()
+            .map(|article_tag| match self.tag(*article_tag) {
+                Some(tag) => (tag.id, tag.similar_tags.clone()),
+                None => (*article_tag, Vec::new()),
             })
+            .collect()
+    }
+
+    // Filter out other players, we don't trust their data anyway
+    // Filter out listings the strategy wants to ignore (e.g. our bedazzlement listings)
+    pub fn get_own_listings(&self, ignored_listings: &[ListingId]) -> Vec<Listing> {
+        self.listings_of_player(self.player.id)
+            .filter(|listing| !ignored_listings.contains(&listing.id))
             .cloned()
             .collect::<Vec<_>>()
     }
 
     // Filter out ourselves
     // Filter out empty listings
-    // Filter out our own bedazzlement listings
-    pub fn get_other_listings(&self) -> Vec<Listing> {
+    // Filter out listings the strategy wants to ignore (e.g. our bedazzlement listings)
+    pub fn get_other_listings(&self, ignored_listings: &[ListingId]) -> Vec<Listing> {
         self.listings
             .iter()
             .filter(|listing| {
                 listing.player != self.player.id
                     && listing.count > 0
-                    && !self.bedazzlement_listings.contains(&listing.id)
+                    && !ignored_listings.contains(&listing.id)
             })
             .cloned()
             .collect::<Vec<_>>()
     }
+
+    pub fn portfolio(&self, own_listings: &[Listing]) -> Portfolio {
+        let mut portfolio = Portfolio::default();
+        for listing in own_listings {
+            let total_price = listing.price * listing.count;
+
+            portfolio.items += listing.count;
+            portfolio.max_value += total_price;
+            portfolio.min_value += match self.article_price_history.get(&listing.article) {
+                Some(history) => history.average_price() * listing.count,
+                None => total_price / 2.0,
+            };
+        }
+        portfolio
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::{TestMarket, PLAYER};
+
+    #[test]
+    fn ranks_suppliers_in_stock_by_price() {
+        let snapshot = TestMarket::default()
+            .article(1, &[], 12.0)
+            .supplier(4, 1, 1, 10.0)
+            .supplier(3, 1, 0, 8.0)
+            .supplier(2, 1, 2, 10.0)
+            .snapshot();
+
+        // Same price, lower id first. Nobody sells what they don't have.
+        assert_eq!(
+            snapshot
+                .suppliers_by_price(ArticleId(1))
+                .iter()
+                .map(|(supplier_id, stock)| (*supplier_id, stock.price))
+                .collect::<Vec<_>>(),
+            vec![
+                (SupplierId(2), Money::from_f64(10.0)),
+                (SupplierId(4), Money::from_f64(10.0)),
+                (SupplierId(1), Money::from_f64(12.0)),
+            ]
+        );
+        assert!(snapshot.suppliers_by_price(ArticleId(2)).is_empty());
+    }
+
+    #[test]
+    fn values_every_unit_of_a_listing() {
+        let snapshot = TestMarket::default()
+            .article(1, &[], 10.0)
+            .listing(1, PLAYER, 1, 3, 25.0)
+            .listing(2, PLAYER, 1, 1, 15.0)
+            .snapshot();
+
+        let portfolio = snapshot.portfolio(&snapshot.get_own_listings(&[]));
+        assert_eq!(portfolio.items, 4);
+        assert_eq!(portfolio.min_value, Money::from_f64(40.0));
+        assert_eq!(portfolio.max_value, Money::from_f64(90.0));
+    }
 }
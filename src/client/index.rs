@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::types::*;
+
+// Lookups into the market data, so strategies don't have to scan the Vecs every tick.
+// The Vecs stay as the API sent them, the indices hold positions into them. Every part is
+// rebuilt on its own when its endpoint returns something new, and sits behind an Arc, so
+// taking a snapshot doesn't copy it.
+#[derive(Debug, Clone, Default)]
+pub struct MarketIndex {
+    articles: Arc<ArticleIndex>,
+    tags: Arc<HashMap<TagId, usize>>,
+    supplier_stock: Arc<HashMap<ArticleId, Vec<(SupplierId, Stock)>>>,
+    listings: Arc<ListingIndex>,
+}
+
+#[derive(Debug, Default)]
+struct ArticleIndex {
+    by_id: HashMap<ArticleId, usize>,
+    by_tag: HashMap<TagId, Vec<usize>>,
+}
+
+#[derive(Debug, Default)]
+struct ListingIndex {
+    by_id: HashMap<ListingId, usize>,
+    by_article: HashMap<ArticleId, Vec<usize>>,
+    by_player: HashMap<PlayerId, Vec<usize>>,
+}
+
+impl MarketIndex {
+    pub fn build(
+        articles: &[Article],
+        tags: &[Tag],
+        suppliers: &[Supplier],
+        listings: &[Listing],
+    ) -> Self {
+        let mut index = Self::default();
+        index.index_articles(articles);
+        index.index_tags(tags);
+        index.index_suppliers(suppliers);
+        index.index_listings(listings);
+        index
+    }
+
+    pub fn index_articles(&mut self, articles: &[Article]) {
+        let mut index = ArticleIndex::default();
+        for (position, article) in articles.iter().enumerate() {
+            index.by_id.insert(article.id, position);
+            for tag in &article.tags {
+                index.by_tag.entry(*tag).or_default().push(position);
+            }
+        }
+        self.articles = Arc::new(index);
+    }
+
+    pub fn index_tags(&mut self, tags: &[Tag]) {
+        self.tags = Arc::new(
+            tags.iter()
+                .enumerate()
+                .map(|(position, tag)| (tag.id, position))
+                .collect(),
+        );
+    }
+
+    // Suppliers keep their order, so the same market always leads to the same buys
+    pub fn index_suppliers(&mut self, suppliers: &[Supplier]) {
+        let mut supplier_stock: HashMap<ArticleId, Vec<(SupplierId, Stock)>> = HashMap::new();
+        for supplier in suppliers {
+            for stock in &supplier.stock {
+                supplier_stock
+                    .entry(stock.article_id)
+                    .or_default()
+                    .push((supplier.id, stock.clone()));
+            }
+        }
+        self.supplier_stock = Arc::new(supplier_stock);
+    }
+
+    pub fn index_listings(&mut self, listings: &[Listing]) {
+        let mut index = ListingIndex::default();
+        for (position, listing) in listings.iter().enumerate() {
+            index.by_id.insert(listing.id, position);
+            index
+                .by_article
+                .entry(listing.article)
+                .or_default()
+                .push(position);
+            index
+                .by_player
+                .entry(listing.player)
+                .or_default()
+                .push(position);
+        }
+        self.listings = Arc::new(index);
+    }
+
+    pub fn article<'a>(
+        &self,
+        articles: &'a [Article],
+        article_id: ArticleId,
+    ) -> Option<&'a Article> {
+        let position = *self.articles.by_id.get(&article_id)?;
+        articles.get(position)
+    }
+
+    // In catalog order
+    pub fn articles_with_tag<'a>(
+        &'a self,
+        articles: &'a [Article],
+        tag_id: TagId,
+    ) -> impl Iterator<Item = &'a Article> {
+        positions(&self.articles.by_tag, &tag_id)
+            .iter()
+            .filter_map(move |position| articles.get(*position))
+    }
+
+    pub fn tag<'a>(&self, tags: &'a [Tag], tag_id: TagId) -> Option<&'a Tag> {
+        let position = *self.tags.get(&tag_id)?;
+        tags.get(position)
+    }
+
+    // Every supplier that has the article on offer, with its stock (which may be 0)
+    pub fn supplier_stock(&self, article_id: ArticleId) -> &[(SupplierId, Stock)] {
+        self.supplier_stock
+            .get(&article_id)
+            .map_or(&[], |supplier_stock| supplier_stock.as_slice())
+    }
+
+    pub fn listing<'a>(
+        &self,
+        listings: &'a [Listing],
+        listing_id: ListingId,
+    ) -> Option<&'a Listing> {
+        let position = *self.listings.by_id.get(&listing_id)?;
+        listings.get(position)
+    }
+
+    pub fn listings_for_article<'a>(
+        &'a self,
+        listings: &'a [Listing],
+        article_id: ArticleId,
+    ) -> impl Iterator<Item = &'a Listing> {
+        positions(&self.listings.by_article, &article_id)
+            .iter()
+            .filter_map(move |position| listings.get(*position))
+    }
+
+    pub fn listings_of_player<'a>(
+        &'a self,
+        listings: &'a [Listing],
+        player_id: PlayerId,
+    ) -> impl Iterator<Item = &'a Listing> {
+        positions(&self.listings.by_player, &player_id)
+            .iter()
+            .filter_map(move |position| listings.get(*position))
+    }
+}
+
+fn positions<'a, K: std::hash::Hash + Eq>(
+    index: &'a HashMap<K, Vec<usize>>,
+    key: &K,
+) -> &'a [usize] {
+    index.get(key).map_or(&[], |positions| positions.as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::MarketState;
+    use crate::game::testing::{TestMarket, OTHER_PLAYER, PLAYER};
+
+    fn market() -> TestMarket {
+        TestMarket::default()
+            .tag(1, &[2])
+            .tag(2, &[1])
+            .article(1, &[1], 10.0)
+            .article(2, &[1, 2], 20.0)
+            .article(3, &[], 30.0)
+            .supplier(2, 2, 4, 18.0)
+            .listing(1, PLAYER, 1, 2, 15.0)
+            .listing(2, OTHER_PLAYER, 1, 1, 14.0)
+            .listing(3, PLAYER, 2, 0, 25.0)
+    }
+
+    // Every lookup finds what a scan of the Vecs finds, in the same order
+    fn assert_agrees(state: &MarketState) {
+        let index = state.index();
+
+        for article in &state.articles {
+            assert_eq!(index.article(&state.articles, article.id), Some(article));
+        }
+        assert_eq!(index.article(&state.articles, ArticleId(99)), None);
+        for tag in &state.tags {
+            assert_eq!(index.tag(&state.tags, tag.id), Some(tag));
+            assert_eq!(
+                index
+                    .articles_with_tag(&state.articles, tag.id)
+                    .collect::<Vec<_>>(),
+                state
+                    .articles
+                    .iter()
+                    .filter(|article| article.tags.contains(&tag.id))
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        for article in &state.articles {
+            assert_eq!(
+                index.supplier_stock(article.id),
+                state
+                    .suppliers
+                    .iter()
+                    .flat_map(|supplier| supplier
+                        .stock
+                        .iter()
+                        .filter(|stock| stock.article_id == article.id)
+                        .map(|stock| (supplier.id, stock.clone())))
+                    .collect::<Vec<_>>()
+            );
+            assert_eq!(
+                index
+                    .listings_for_article(&state.listings, article.id)
+                    .collect::<Vec<_>>(),
+                state
+                    .listings
+                    .iter()
+                    .filter(|listing| listing.article == article.id)
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        for listing in &state.listings {
+            assert_eq!(index.listing(&state.listings, listing.id), Some(listing));
+        }
+        for player_id in [PLAYER, OTHER_PLAYER] {
+            assert_eq!(
+                index
+                    .listings_of_player(&state.listings, player_id)
+                    .collect::<Vec<_>>(),
+                state
+                    .listings
+                    .iter()
+                    .filter(|listing| listing.player == player_id)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_market_data() {
+        let mut state = MarketState::default();
+        market().fetch_into(&mut state);
+
+        assert_agrees(&state);
+        // Both suppliers have article 2
+        assert_eq!(state.index().supplier_stock(ArticleId(2)).len(), 2);
+    }
+
+    #[test]
+    fn follows_listing_and_supplier_updates() {
+        let mut state = MarketState::default();
+        market().fetch_into(&mut state);
+
+        // Listing 1 is gone, the others moved and a new one came in front
+        let mut listings = state.listings.clone();
+        listings.remove(0);
+        listings.insert(
+            0,
+            Listing {
+                id: ListingId(4),
+                player: OTHER_PLAYER,
+                article: ArticleId(3),
+                count: 5,
+                price: Money::from_f64(35.0),
+            },
+        );
+        state.update_listings(listings);
+        assert_agrees(&state);
+        assert_eq!(state.index().listing(&state.listings, ListingId(1)), None);
+        assert_eq!(
+            state
+                .index()
+                .listings_of_player(&state.listings, PLAYER)
+                .map(|listing| listing.id)
+                .collect::<Vec<_>>(),
+            vec![ListingId(3)]
+        );
+
+        // Supplier 2 sold out of article 2 and now also has article 3
+        let mut suppliers = state.suppliers.clone();
+        suppliers[1].stock = vec![Stock {
+            article_id: ArticleId(3),
+            stock: 1,
+            price: Money::from_f64(28.0),
+        }];
+        state.update_suppliers(suppliers);
+        assert_agrees(&state);
+        assert_eq!(state.index().supplier_stock(ArticleId(2)).len(), 1);
+        assert_eq!(
+            state.index().supplier_stock(ArticleId(3))[1].0,
+            SupplierId(2)
+        );
+    }
+}
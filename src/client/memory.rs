@@ -0,0 +1,396 @@
+use reqwest::StatusCode;
+
+use super::{types::*, ClientError, MarketApi, MarketState};
+
+// A marketplace that only lives in memory. It follows the rules of the hackathon server as
+// far as we know them, so strategies can be run without a live server.
+pub struct MemoryMarket {
+    pub articles: Vec<Article>,
+    pub tags: Vec<Tag>,
+    pub suppliers: Vec<Supplier>,
+    pub players: Vec<Player>,
+    pub listings: Vec<Listing>,
+
+    next_listing_id: usize,
+}
+
+impl MemoryMarket {
+    pub fn new(articles: Vec<Article>, tags: Vec<Tag>, suppliers: Vec<Supplier>) -> Self {
+        Self {
+            articles,
+            tags,
+            suppliers,
+            players: Vec::new(),
+            listings: Vec::new(),
+
+            next_listing_id: 1,
+        }
+    }
+
+    pub fn add_player(&mut self, money: Money) -> PlayerId {
+        let id = PlayerId(
+            self.players
+                .iter()
+                .map(|player| player.id.0)
+                .max()
+                .unwrap_or(0)
+                + 1,
+        );
+        self.players.push(Player {
+            id,
+            money,
+            stock: Vec::new(),
+        });
+        id
+    }
+
+    // Listing ids up to this one belong to someone else, e.g. listings replayed from a recording
+    pub fn reserve_listing_ids(&mut self, up_to: ListingId) {
+        self.next_listing_id = self.next_listing_id.max(up_to.0 + 1);
+    }
+
+    pub fn player(&self, player_id: PlayerId) -> Option<&Player> {
+        self.players.iter().find(|player| player.id == player_id)
+    }
+
+    fn player_mut(&mut self, player_id: PlayerId) -> Option<&mut Player> {
+        self.players
+            .iter_mut()
+            .find(|player| player.id == player_id)
+    }
+
+    pub fn buy_from_supplier(
+        &mut self,
+        player_id: PlayerId,
+        supplier_id: SupplierId,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> bool {
+        let stock = match self
+            .suppliers
+            .iter_mut()
+            .find(|supplier| supplier.id == supplier_id)
+            .and_then(|supplier| {
+                supplier
+                    .stock
+                    .iter_mut()
+                    .find(|stock| stock.article_id == article_id)
+            }) {
+            Some(stock) => stock,
+            None => return false,
+        };
+
+        // The supplier won't sell below his own price or more than he has
+        if count == 0 || stock.stock < count || price_per_unit < stock.price {
+            return false;
+        }
+        let total_price = stock.price * count;
+
+        let player = match self
+            .players
+            .iter_mut()
+            .find(|player| player.id == player_id)
+        {
+            Some(player) => player,
+            None => return false,
+        };
+        if player.money < total_price {
+            return false;
+        }
+
+        stock.stock -= count;
+        player.money -= total_price;
+        add_player_stock(player, article_id, count);
+
+        true
+    }
+
+    pub fn create_listing(
+        &mut self,
+        player_id: PlayerId,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> Option<ListingId> {
+        let player = self.player_mut(player_id)?;
+        if !take_player_stock(player, article_id, count) {
+            return None;
+        }
+
+        let id = ListingId(self.next_listing_id);
+        self.next_listing_id += 1;
+        self.listings.push(Listing {
+            id,
+            player: player_id,
+            article: article_id,
+            count,
+            price: price_per_unit,
+        });
+
+        Some(id)
+    }
+
+    pub fn update_listing(
+        &mut self,
+        player_id: PlayerId,
+        listing_id: ListingId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> bool {
+        let (article_id, old_count) = match self
+            .listings
+            .iter()
+            .find(|listing| listing.id == listing_id && listing.player == player_id)
+        {
+            Some(listing) => (listing.article, listing.count),
+            None => return false,
+        };
+
+        // Listed articles come from and go back to the player stock
+        let player = match self.player_mut(player_id) {
+            Some(player) => player,
+            None => return false,
+        };
+        if count > old_count {
+            if !take_player_stock(player, article_id, count - old_count) {
+                return false;
+            }
+        } else {
+            add_player_stock(player, article_id, old_count - count);
+        }
+
+        if let Some(listing) = self
+            .listings
+            .iter_mut()
+            .find(|listing| listing.id == listing_id)
+        {
+            listing.count = count;
+            listing.price = price_per_unit;
+        }
+
+        true
+    }
+
+    pub fn delete_listing(&mut self, player_id: PlayerId, listing_id: ListingId) -> bool {
+        let index = match self
+            .listings
+            .iter()
+            .position(|listing| listing.id == listing_id && listing.player == player_id)
+        {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let listing = self.listings.remove(index);
+        if let Some(player) = self.player_mut(player_id) {
+            add_player_stock(player, listing.article, listing.count);
+        }
+
+        true
+    }
+
+    // A customer buys from a listing, the money goes straight to the seller
+    pub fn sell_from_listing(&mut self, listing_id: ListingId, count: usize) -> bool {
+        let listing = match self
+            .listings
+            .iter_mut()
+            .find(|listing| listing.id == listing_id)
+        {
+            Some(listing) => listing,
+            None => return false,
+        };
+        if count == 0 || listing.count < count {
+            return false;
+        }
+
+        listing.count -= count;
+        let (seller, earnings) = (listing.player, listing.price * count);
+
+        if let Some(player) = self.player_mut(seller) {
+            player.money += earnings;
+        }
+
+        true
+    }
+}
+
+fn add_player_stock(player: &mut Player, article_id: ArticleId, count: usize) {
+    if count == 0 {
+        return;
+    }
+    match player
+        .stock
+        .iter_mut()
+        .find(|player_stock| player_stock.article_id == article_id)
+    {
+        Some(player_stock) => player_stock.stock += count,
+        None => player.stock.push(PlayerStock {
+            article_id,
+            stock: count,
+        }),
+    }
+}
+
+fn take_player_stock(player: &mut Player, article_id: ArticleId, count: usize) -> bool {
+    if count == 0 {
+        return true;
+    }
+    let index = match player
+        .stock
+        .iter()
+        .position(|player_stock| player_stock.article_id == article_id)
+    {
+        Some(index) => index,
+        None => return false,
+    };
+    if player.stock[index].stock < count {
+        return false;
+    }
+
+    player.stock[index].stock -= count;
+    if player.stock[index].stock == 0 {
+        // The server doesn't list empty stock
+        player.stock.remove(index);
+    }
+
+    true
+}
+
+// MarketApi implementation on top of a MemoryMarket, playing as one of its players
+pub struct MemoryClient {
+    pub market: MemoryMarket,
+    player_id: PlayerId,
+
+    state: MarketState,
+}
+
+impl MemoryClient {
+    pub fn new(market: MemoryMarket, player_id: PlayerId) -> Option<Self> {
+        let player = market.player(player_id)?.clone();
+
+        let mut state = MarketState::default();
+        state.update_player(player);
+        state.update_articles(market.articles.clone());
+        state.update_tags(market.tags.clone());
+        state.update_suppliers(market.suppliers.clone());
+        state.update_listings(market.listings.clone());
+
+        Some(Self {
+            market,
+            player_id,
+            state,
+        })
+    }
+}
+
+// The memory market doesn't tell us why it refused, the real server doesn't either
+fn rejected(endpoint: String) -> ClientError {
+    ClientError::Status {
+        endpoint,
+        status: StatusCode::BAD_REQUEST,
+        body: String::new(),
+    }
+}
+
+impl MarketApi for MemoryClient {
+    fn state(&self) -> &MarketState {
+        &self.state
+    }
+
+    fn state_mut(&mut self) -> &mut MarketState {
+        &mut self.state
+    }
+
+    async fn fetch_player_self(&mut self) -> Result<(), ClientError> {
+        match self.market.player(self.player_id) {
+            Some(player) => {
+                self.state.update_player(player.clone());
+                Ok(())
+            }
+            None => Err(ClientError::Status {
+                endpoint: "/player/self".to_string(),
+                status: StatusCode::UNAUTHORIZED,
+                body: String::new(),
+            }),
+        }
+    }
+
+    async fn fetch_articles(&mut self) -> Result<(), ClientError> {
+        self.state.update_articles(self.market.articles.clone());
+        Ok(())
+    }
+
+    async fn fetch_tags(&mut self) -> Result<(), ClientError> {
+        self.state.update_tags(self.market.tags.clone());
+        Ok(())
+    }
+
+    async fn fetch_suppliers(&mut self) -> Result<(), ClientError> {
+        self.state.update_suppliers(self.market.suppliers.clone());
+        Ok(())
+    }
+
+    async fn fetch_listings(&mut self) -> Result<(), ClientError> {
+        self.state.update_listings(self.market.listings.clone());
+        Ok(())
+    }
+
+    async fn buy_from_supplier(
+        &mut self,
+        supplier_id: SupplierId,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> Result<(), ClientError> {
+        if self.market.buy_from_supplier(
+            self.player_id,
+            supplier_id,
+            article_id,
+            count,
+            price_per_unit,
+        ) {
+            Ok(())
+        } else {
+            Err(rejected(format!(
+                "/supplier/{}/article/{}/buy",
+                supplier_id, article_id
+            )))
+        }
+    }
+
+    async fn create_listing(
+        &mut self,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> Result<ListingId, ClientError> {
+        self.market
+            .create_listing(self.player_id, article_id, count, price_per_unit)
+            .ok_or_else(|| rejected("/listing/new".to_string()))
+    }
+
+    async fn _delete_listing(&mut self, listing_id: ListingId) -> Result<(), ClientError> {
+        if self.market.delete_listing(self.player_id, listing_id) {
+            Ok(())
+        } else {
+            Err(rejected(format!("/listing/{}", listing_id)))
+        }
+    }
+
+    async fn update_listing(
+        &mut self,
+        listing_id: ListingId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> Result<(), ClientError> {
+        if self
+            .market
+            .update_listing(self.player_id, listing_id, count, price_per_unit)
+        {
+            Ok(())
+        } else {
+            Err(rejected(format!("/listing/{}", listing_id)))
+        }
+    }
+}
@@ -1,68 +1,59 @@
+mod api;
+mod error;
+mod fetch;
 mod helper;
+mod ids;
+mod index;
+pub mod memory;
+mod money;
+mod ratelimit;
+mod retry;
+mod snapshot;
+mod state;
+mod stats;
+mod tape;
 pub mod types;
 
 use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
 
 use reqwest::StatusCode;
-
+use serde::de::DeserializeOwned;
+use tracing::{debug, instrument, warn};
+
+pub use self::api::MarketApi;
+pub use self::error::ClientError;
+pub use self::fetch::{FailedFetch, FetchReport, MarketPart};
+pub use self::helper::Portfolio;
+pub use self::index::MarketIndex;
+pub use self::money::Money;
+pub use self::ratelimit::{
+    endpoint_key, RateLimit, RateLimitStats, RateLimiter, DEFAULT_ENDPOINT_LIMITS,
+    DEFAULT_REQUESTS_PER_SECOND,
+};
+pub use self::retry::RetryPolicy;
+pub use self::snapshot::MarketSnapshot;
+pub use self::state::{ArticlePriceHistory, MarketState};
+pub use self::stats::{RequestLog, RequestStats};
+pub use self::tape::{TapeEntry, TapePlayer, TapeRecorder};
 use self::types::*;
 
 static USER_AGENT: &str = "marketplacesimulation-client-kromlinger-justin/0.1.0";
 static HACKATHON_API_URL: &str = "https://hackathon-game.relaxdays.cloud";
 
-macro_rules! error_failed_to_receive_response {
-    () => {
-        "{}: Failed to receive response, failing silently ({})"
-    };
-}
-
-macro_rules! error_failed_to_parse_type {
-    () => {
-        "{}: Failed to parse type, failing silently ({})"
-    };
-}
-
 pub struct Client {
     api_url: String,
     reqwest_client: reqwest::Client,
     user_id: String,
     api_key: String,
+    retry_policy: RetryPolicy,
+    rate_limiter: RateLimiter,
+    request_log: RequestLog,
+    tape_recorder: Option<TapeRecorder>,
+    tape_player: Option<TapePlayer>,
 
-    pub player: Player,
-    pub articles: Vec<Article>,
-    pub article_price_history: HashMap<usize, ArticlePriceHistory>,
-    pub tags: Vec<Tag>,
-    pub tag_trend_levels: HashMap<usize, usize>,
-    pub suppliers: Vec<Supplier>,
-    pub listings: Vec<Listing>,
-
-    pub bedazzlement_listings: Vec<usize>,
-}
-
-pub struct ArticlePriceHistory {
-    pub supplier_price_history: Vec<f64>,
-}
-
-impl ArticlePriceHistory {
-    pub fn new(supplier_price: f64) -> Self {
-        Self {
-            supplier_price_history: vec![supplier_price],
-        }
-    }
-
-    pub fn average_price(&self) -> f64 {
-        let mut sum = 0.0;
-        for price in &self.supplier_price_history {
-            sum += price;
-        }
-        sum / self.supplier_price_history.len() as f64
-    }
-
-    fn record_supplier_price(&mut self, supplier_price: f64) {
-        if self.supplier_price_history.last() != Some(&supplier_price) {
-            self.supplier_price_history.push(supplier_price);
-        }
-    }
+    state: MarketState,
 }
 
 impl Client {
@@ -70,6 +61,27 @@ impl Client {
         api_url: Option<String>,
         user_id: String,
         api_key: String,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::recording(
+            api_url,
+            user_id,
+            api_key,
+            None,
+            RetryPolicy::default(),
+            RateLimiter::default(),
+        )
+        .await
+    }
+
+    // Like new, but every exchange with the server is appended to the tape, if there is one.
+    // The retry policy and the rate limiter apply from the first request on.
+    pub async fn recording(
+        api_url: Option<String>,
+        user_id: String,
+        api_key: String,
+        tape: Option<&Path>,
+        retry_policy: RetryPolicy,
+        rate_limiter: RateLimiter,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let reqwest_client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
 
@@ -78,69 +90,77 @@ impl Client {
             None => HACKATHON_API_URL.to_string(),
         };
 
+        let tape_recorder = match tape {
+            Some(tape) => Some(TapeRecorder::create(tape)?),
+            None => None,
+        };
+
         let mut client = Self {
             api_url,
             reqwest_client,
             user_id,
             api_key,
+            retry_policy,
+            rate_limiter,
+            request_log: RequestLog::default(),
+            tape_recorder,
+            tape_player: None,
 
-            articles: Vec::new(),
-            article_price_history: HashMap::new(),
-            tags: Vec::new(),
-            tag_trend_levels: HashMap::new(),
-            suppliers: Vec::new(),
-            player: Player {
-                id: 0,
-                money: std::f64::MAX, // Unrealistic number, so we can initialize cleanly
-                stock: Vec::new(),
-            },
-            listings: Vec::new(),
-
-            bedazzlement_listings: Vec::new(),
+            state: MarketState::default(),
         };
+        client.initialize().await?;
 
-        // This will be the only time we return a hard error
-        if !client.fetch_player_self().await {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to fetch player self",
-            )));
-        }
-        if !client.fetch_articles().await {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to fetch articles",
-            )));
-        }
-        if !client.fetch_tags().await {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to fetch tags",
-            )));
-        }
-        if !client.fetch_suppliers().await {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to fetch suppliers",
-            )));
-        }
-        if !client.fetch_listings().await {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to fetch listings",
-            )));
-        }
+        Ok(client)
+    }
+
+    // Plays back a recorded session instead of talking to a server. Retries and waits for the
+    // rate limiter are on the tape already.
+    pub async fn replay(tape: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut client = Self {
+            // Never contacted, but requests still need a valid URL to be built
+            api_url: "http://replay.invalid".to_string(),
+            reqwest_client: reqwest::Client::builder().user_agent(USER_AGENT).build()?,
+            user_id: String::new(),
+            api_key: String::new(),
+            retry_policy: RetryPolicy::none(),
+            rate_limiter: RateLimiter::unlimited(),
+            request_log: RequestLog::default(),
+            tape_recorder: None,
+            tape_player: Some(TapePlayer::load(tape)?),
+
+            state: MarketState::default(),
+        };
+        client.initialize().await?;
 
         Ok(client)
     }
 
+    async fn initialize(&mut self) -> Result<(), ClientError> {
+        // Without a complete picture of the market we can't start
+        self.fetch_player_self().await?;
+        self.fetch_articles().await?;
+        self.fetch_tags().await?;
+        self.fetch_suppliers().await?;
+        self.fetch_listings().await?;
+
+        Ok(())
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) {
+        self.rate_limiter = rate_limiter;
+    }
+
     fn requestbuilder(
         &self,
         endpoint: &str,
         request_type: reqwest::Method,
     ) -> reqwest::RequestBuilder {
         self.reqwest_client
-            .request(request_type, &format!("{}{}", self.api_url, endpoint))
+            .request(request_type, format!("{}{}", self.api_url, endpoint))
     }
 
     fn authorized_requestbuilder(
@@ -152,306 +172,336 @@ impl Client {
             .basic_auth(&self.user_id, Some(&self.api_key))
     }
 
-    pub async fn fetch_articles(&mut self) -> bool {
-        let endpoint = "/article";
-
-        let res = match self
-            .requestbuilder(endpoint, reqwest::Method::GET)
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
+    // GET one part of the market. Only the player needs authorization.
+    async fn get<T: DeserializeOwned>(&self, part: MarketPart) -> Result<T, ClientError> {
+        let endpoint = part.endpoint();
+        let request = match part {
+            MarketPart::Player => self.authorized_requestbuilder(endpoint, reqwest::Method::GET),
+            _ => self.requestbuilder(endpoint, reqwest::Method::GET),
         };
 
-        let status = res.status();
+        let body = self.send(endpoint, request, true).await?;
+        parse(endpoint, &body)
+    }
 
-        let latest = match res.json::<Vec<Article>>().await {
-            Ok(parsed_type) => parsed_type,
-            Err(e) => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, e);
-                return false;
-            }
-        };
+    async fn get_player_self(&self) -> Result<Player, ClientError> {
+        let endpoint = MarketPart::Player.endpoint();
 
-        self.articles = latest.clone();
+        let mut latest = self.get::<Vec<Player>>(MarketPart::Player).await?;
+        if latest.len() != 1 {
+            return Err(ClientError::UnexpectedShape {
+                endpoint: endpoint.to_string(),
+                reason: format!("Expected one player, got {}", latest.len()),
+            });
+        }
 
-        status == StatusCode::OK
+        Ok(latest.pop().unwrap())
     }
 
-    pub async fn fetch_tags(&mut self) -> bool {
-        let endpoint = "/tag";
-
-        let res = match self
-            .requestbuilder(endpoint, reqwest::Method::GET)
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+    // Send a request and return the body of a 200 OK response.
+    // Failed attempts are repeated according to our retry policy.
+    #[instrument(level = "debug", name = "request", skip_all, fields(endpoint = endpoint))]
+    async fn send(
+        &self,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+        idempotent: bool,
+    ) -> Result<String, ClientError> {
+        if self.tape_player.is_none() && self.tape_recorder.is_none() {
+            return self.send_with_retries(endpoint, request, idempotent).await;
+        }
 
-        let status = res.status();
+        let (method, body) = describe(&request);
+        if let Some(tape_player) = &self.tape_player {
+            return tape_player.replay(&method, endpoint);
+        }
 
-        let latest = match res.json::<Vec<Tag>>().await {
-            Ok(parsed_type) => parsed_type,
-            Err(e) => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, e);
-                return false;
+        let result = self.send_with_retries(endpoint, request, idempotent).await;
+        if let Some(tape_recorder) = &self.tape_recorder {
+            let entry = TapeEntry::new(method, endpoint, body, &result);
+            if let Err(e) = tape_recorder.record(&entry) {
+                warn!(endpoint, error = %e, "Failed to record exchange");
             }
-        };
+        }
 
-        if self.tags != latest {
-            // Tag-List change, initialize the tag trend levels
-            for tag in &latest {
-                if !self.tag_trend_levels.contains_key(&tag.id) {
-                    self.tag_trend_levels.insert(tag.id, 0);
+        result
+    }
+
+    async fn send_with_retries(
+        &self,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+        idempotent: bool,
+    ) -> Result<String, ClientError> {
+        let mut retry = 0;
+        loop {
+            let attempt = match request.try_clone() {
+                Some(attempt) => attempt,
+                // Streaming bodies can't be cloned, we don't use those anyway
+                None => return self.send_once(endpoint, request).await,
+            };
+
+            match self.send_once(endpoint, attempt).await {
+                Err(e)
+                    if retry + 1 < self.retry_policy.max_attempts
+                        && self.retry_policy.is_retryable(&e, idempotent) =>
+                {
+                    retry += 1;
+                    let delay = self.retry_policy.delay(retry);
+                    warn!(
+                        endpoint,
+                        error = %e,
+                        retry,
+                        delay_ms = delay.as_millis() as u64,
+                        "Retrying"
+                    );
+                    tokio::time::sleep(delay).await;
                 }
+                result => return result,
             }
         }
-
-        self.tags = latest.clone();
-
-        status == StatusCode::OK
     }
 
-    pub async fn fetch_suppliers(&mut self) -> bool {
-        let endpoint = "/supplier";
+    async fn send_once(
+        &self,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<String, ClientError> {
+        // Every attempt counts against our budget, retries included
+        self.rate_limiter.acquire(endpoint).await;
+
+        let sent = Instant::now();
+        let result = self.exchange(endpoint, request).await;
+        self.request_log
+            .record(endpoint, sent.elapsed(), result.is_ok());
+        result
+    }
 
-        let res = match self
-            .requestbuilder(endpoint, reqwest::Method::GET)
+    async fn exchange(
+        &self,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<String, ClientError> {
+        let res = request
             .send()
             .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+            .map_err(|source| ClientError::Transport {
+                endpoint: endpoint.to_string(),
+                source,
+            })?;
 
         let status = res.status();
+        let body = res.text().await.map_err(|source| ClientError::Transport {
+            endpoint: endpoint.to_string(),
+            source,
+        })?;
+
+        debug!(status = status.as_u16(), "Response");
+
+        if status != StatusCode::OK {
+            return Err(ClientError::Status {
+                endpoint: endpoint.to_string(),
+                status,
+                body,
+            });
+        }
 
-        let latest = match res.json::<Vec<Supplier>>().await {
-            Ok(parsed_type) => parsed_type,
-            Err(e) => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, e);
-                return false;
-            }
-        };
+        Ok(body)
+    }
+}
 
-        if self.suppliers != latest {
-            // Supplier-Stock changes, save potential article price changes
-            // WARNING: Due to a bug that won't be fixed, all suppliers have the same price for an article
-            latest
-                .iter()
-                .flat_map(|supplier| {
-                    supplier
-                        .stock
-                        .iter()
-                        .map(|stock| (stock.article_id, stock.price))
-                })
-                .for_each(|(article_id, price)| {
-                    self.article_price_history
-                        .entry(article_id)
-                        .and_modify(|article_price_history| {
-                            article_price_history.record_supplier_price(price)
-                        })
-                        .or_insert_with(|| ArticlePriceHistory::new(price));
-                });
-        }
+// Method and JSON body of a request, for the tape
+fn describe(request: &reqwest::RequestBuilder) -> (String, Option<serde_json::Value>) {
+    let request = match request.try_clone().and_then(|request| request.build().ok()) {
+        Some(request) => request,
+        None => return (String::new(), None),
+    };
 
-        self.suppliers = latest.clone();
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .and_then(|body| serde_json::from_slice(body).ok());
 
-        status == StatusCode::OK
-    }
+    (request.method().to_string(), body)
+}
 
-    pub async fn buy_from_supplier(
-        &mut self,
-        supplier_id: usize,
-        article_id: usize,
-        count: usize,
-        price_per_unit: f64,
-    ) -> bool {
-        let endpoint = format!("/supplier/{}/article/{}/buy", supplier_id, article_id);
+fn parse<T: DeserializeOwned>(endpoint: &str, body: &str) -> Result<T, ClientError> {
+    serde_json::from_str(body).map_err(|source| ClientError::Decode {
+        endpoint: endpoint.to_string(),
+        source,
+    })
+}
 
-        let res = match self
-            .authorized_requestbuilder(&endpoint, reqwest::Method::POST)
-            .json(&BuyFromSupplierBody {
-                count,
-                price_per_unit,
-            })
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+impl MarketApi for Client {
+    fn state(&self) -> &MarketState {
+        &self.state
+    }
 
-        res.status() == StatusCode::OK
+    fn state_mut(&mut self) -> &mut MarketState {
+        &mut self.state
     }
 
-    pub async fn fetch_player_self(&mut self) -> bool {
-        let endpoint = "/player/self";
+    fn rate_limit_stats(&self) -> HashMap<String, RateLimitStats> {
+        self.rate_limiter.stats()
+    }
 
-        let res = match self
-            .authorized_requestbuilder(endpoint, reqwest::Method::GET)
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+    fn request_stats(&self) -> HashMap<String, RequestStats> {
+        self.request_log.stats()
+    }
 
-        let status = res.status();
+    fn now(&self) -> Instant {
+        match &self.tape_player {
+            Some(tape_player) => tape_player.now(),
+            None => Instant::now(),
+        }
+    }
 
-        let mut latest = match res.json::<Vec<Player>>().await {
-            Ok(parsed_type) => parsed_type,
-            Err(e) => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, e);
-                return false;
-            }
-        };
+    async fn fetch_player_self(&mut self) -> Result<(), ClientError> {
+        let player = self.get_player_self().await?;
+        self.state.update_player(player);
+        Ok(())
+    }
 
-        if latest.len() != 1 {
-            eprintln!(
-                error_failed_to_parse_type!(),
-                endpoint, "Expected one player"
-            );
-            return false;
-        }
+    async fn fetch_articles(&mut self) -> Result<(), ClientError> {
+        let articles = self.get(MarketPart::Articles).await?;
+        self.state.update_articles(articles);
+        Ok(())
+    }
 
-        self.player = latest.pop().unwrap().clone();
+    async fn fetch_tags(&mut self) -> Result<(), ClientError> {
+        let tags = self.get(MarketPart::Tags).await?;
+        self.state.update_tags(tags);
+        Ok(())
+    }
 
-        status == StatusCode::OK
+    async fn fetch_suppliers(&mut self) -> Result<(), ClientError> {
+        let suppliers = self.get(MarketPart::Suppliers).await?;
+        self.state.update_suppliers(suppliers);
+        Ok(())
     }
 
-    pub async fn fetch_listings(&mut self) -> bool {
-        let endpoint = "/listing";
+    async fn fetch_listings(&mut self) -> Result<(), ClientError> {
+        let listings = self.get(MarketPart::Listings).await?;
+        self.state.update_listings(listings);
+        Ok(())
+    }
 
-        let res = match self
-            .requestbuilder(endpoint, reqwest::Method::GET)
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+    // All five requests at once, so the parts fit together and we only wait for the slowest
+    async fn fetch_market(&mut self) -> Result<FetchReport, ClientError> {
+        let mut report = FetchReport::start();
+        let (player, articles, tags, suppliers, listings) = tokio::join!(
+            self.get_player_self(),
+            self.get::<Vec<Article>>(MarketPart::Articles),
+            self.get::<Vec<Tag>>(MarketPart::Tags),
+            self.get::<Vec<Supplier>>(MarketPart::Suppliers),
+            self.get::<Vec<Listing>>(MarketPart::Listings),
+        );
+
+        // Nothing is applied before every answer is in, so a fatal error leaves the state alone
+        let player = report.record(MarketPart::Player, player)?;
+        let articles = report.record(MarketPart::Articles, articles)?;
+        let tags = report.record(MarketPart::Tags, tags)?;
+        let suppliers = report.record(MarketPart::Suppliers, suppliers)?;
+        let listings = report.record(MarketPart::Listings, listings)?;
+
+        if let Some(player) = player {
+            self.state.update_player(player);
+        }
+        if let Some(articles) = articles {
+            self.state.update_articles(articles);
+        }
+        if let Some(tags) = tags {
+            self.state.update_tags(tags);
+        }
+        if let Some(suppliers) = suppliers {
+            self.state.update_suppliers(suppliers);
+        }
+        if let Some(listings) = listings {
+            self.state.update_listings(listings);
+        }
 
-        let status = res.status();
+        self.state.last_fetch = report.clone();
+        Ok(report)
+    }
 
-        let latest = match res.json::<Vec<Listing>>().await {
-            Ok(parsed_type) => parsed_type,
-            Err(e) => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, e);
-                return false;
-            }
-        };
+    async fn buy_from_supplier(
+        &mut self,
+        supplier_id: SupplierId,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    ) -> Result<(), ClientError> {
+        let endpoint = format!("/supplier/{}/article/{}/buy", supplier_id, article_id);
 
-        self.listings = latest.clone();
+        let request = self
+            .authorized_requestbuilder(&endpoint, reqwest::Method::POST)
+            .json(&BuyFromSupplierBody {
+                count,
+                price_per_unit,
+            });
+        // Buying twice would be expensive, so never retry blindly
+        self.send(&endpoint, request, false).await?;
 
-        status == StatusCode::OK
+        Ok(())
     }
 
-    pub async fn create_listing(
+    async fn create_listing(
         &mut self,
-        article_id: usize,
+        article_id: ArticleId,
         count: usize,
-        price_per_unit: f64,
-    ) -> Option<usize> {
+        price_per_unit: Money,
+    ) -> Result<ListingId, ClientError> {
         let endpoint = "/listing/new";
 
-        let res = match self
-            .authorized_requestbuilder(&endpoint, reqwest::Method::POST)
+        let request = self
+            .authorized_requestbuilder(endpoint, reqwest::Method::POST)
             .json(&CreateListingBody {
                 article: article_id,
                 count,
                 price: price_per_unit,
-            })
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return None;
-            }
-        };
-
-        let json = match res.json::<HashMap<String, usize>>().await {
-            Ok(parsed_type) => parsed_type,
-            Err(e) => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, e);
-                return None;
-            }
-        };
+            });
+        let body = self.send(endpoint, request, false).await?;
+        let json = parse::<HashMap<String, ListingId>>(endpoint, &body)?;
 
         match json.get("id") {
-            Some(id) => Some(*id),
-            None => {
-                eprintln!(error_failed_to_parse_type!(), endpoint, "No id found");
-                None
-            }
+            Some(id) => Ok(*id),
+            None => Err(ClientError::UnexpectedShape {
+                endpoint: endpoint.to_string(),
+                reason: "No id found".to_string(),
+            }),
         }
     }
 
-    pub async fn _delete_listing(&mut self, listing_id: usize) -> bool {
+    async fn _delete_listing(&mut self, listing_id: ListingId) -> Result<(), ClientError> {
         let endpoint = format!("/listing/{}", listing_id);
 
-        let res = match self
-            .authorized_requestbuilder(&endpoint, reqwest::Method::DELETE)
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+        self.send(
+            &endpoint,
+            self.authorized_requestbuilder(&endpoint, reqwest::Method::DELETE),
+            false,
+        )
+        .await?;
 
-        res.status() == StatusCode::OK
+        Ok(())
     }
 
-    pub async fn update_listing(
+    async fn update_listing(
         &mut self,
-        listing_id: usize,
+        listing_id: ListingId,
         count: usize,
-        price_per_unit: f64,
-    ) -> bool {
+        price_per_unit: Money,
+    ) -> Result<(), ClientError> {
         let endpoint = format!("/listing/{}", listing_id);
 
-        let res = match self
+        let request = self
             .authorized_requestbuilder(&endpoint, reqwest::Method::PUT)
             .json(&UpdateListingBody {
                 count,
                 price: price_per_unit,
-            })
-            .send()
-            .await
-        {
-            Ok(res) => res,
-            Err(e) => {
-                eprintln!(error_failed_to_receive_response!(), endpoint, e);
-                return false;
-            }
-        };
+            });
+        // Setting the same count and price twice does no harm
+        self.send(&endpoint, request, true).await?;
 
-        res.status() == StatusCode::OK
+        Ok(())
     }
 }
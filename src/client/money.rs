@@ -0,0 +1,317 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Prices always have a maximum of six digits after the floating point
+const SCALE: i64 = 1_000_000;
+
+// An amount of money with exactly six decimals, stored in millionths.
+// The arithmetic saturates instead of overflowing, so Money::MAX works as a placeholder.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+    pub const MAX: Money = Money(i64::MAX);
+    pub const MIN: Money = Money(i64::MIN);
+
+    pub const fn from_micros(micros: i64) -> Self {
+        Self(micros)
+    }
+
+    pub const fn as_micros(self) -> i64 {
+        self.0
+    }
+
+    // Rounded to the nearest millionth, NaN ends up as zero
+    pub fn from_f64(amount: f64) -> Self {
+        // `as` saturates for values out of range
+        Self((amount * SCALE as f64).round() as i64)
+    }
+
+    pub fn as_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0.saturating_sub(other.0))
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, other: Money) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(self.0.saturating_neg())
+    }
+}
+
+// Price per unit times a count
+impl Mul<usize> for Money {
+    type Output = Money;
+
+    fn mul(self, count: usize) -> Money {
+        let count = i64::try_from(count).unwrap_or(i64::MAX);
+        Money(self.0.saturating_mul(count))
+    }
+}
+
+// For percentages and multipliers, rounded to the nearest millionth
+impl Mul<f64> for Money {
+    type Output = Money;
+
+    fn mul(self, factor: f64) -> Money {
+        Money::from_f64(self.as_f64() * factor)
+    }
+}
+
+impl Div<f64> for Money {
+    type Output = Money;
+
+    fn div(self, divisor: f64) -> Money {
+        Money::from_f64(self.as_f64() / divisor)
+    }
+}
+
+// Rounded to the nearest millionth, dividing by zero gives zero
+impl Div<usize> for Money {
+    type Output = Money;
+
+    fn div(self, count: usize) -> Money {
+        if count == 0 {
+            return Money::ZERO;
+        }
+        let count = i128::try_from(count).unwrap_or(i128::MAX);
+        let micros = self.0 as i128;
+        let half = count / 2;
+        let rounded = if micros >= 0 {
+            (micros + half) / count
+        } else {
+            (micros - half) / count
+        };
+        Money(rounded as i64)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let micros = self.0.unsigned_abs();
+        let units = micros / SCALE as u64;
+        let fraction = micros % SCALE as u64;
+
+        let text = match f.precision() {
+            // {:.2} and friends round like f64 formatting would
+            Some(precision) => format!("{}{:.*}", sign, precision, micros as f64 / SCALE as f64),
+            // Without trailing zeros: 10, 10.5, 0.000001
+            None if fraction == 0 => format!("{}{}", sign, units),
+            None => {
+                let fraction = format!("{:06}", fraction);
+                format!("{}{}.{}", sign, units, fraction.trim_end_matches('0'))
+            }
+        };
+
+        // Numbers are right-aligned unless asked otherwise
+        let width = f.width().unwrap_or(0);
+        match f.align() {
+            Some(fmt::Alignment::Left) => write!(f, "{:<width$}", text),
+            Some(fmt::Alignment::Center) => write!(f, "{:^width$}", text),
+            _ => write!(f, "{:>width$}", text),
+        }
+    }
+}
+
+// The API speaks JSON numbers. Every amount with up to six decimals has a closest f64 that
+// prints back to the same digits, so this round-trips.
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+struct MoneyVisitor;
+
+impl Visitor<'_> for MoneyVisitor {
+    type Value = Money;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an amount of money")
+    }
+
+    fn visit_i64<E: de::Error>(self, units: i64) -> Result<Money, E> {
+        units
+            .checked_mul(SCALE)
+            .map(Money)
+            .ok_or_else(|| E::custom(format!("{} is out of range", units)))
+    }
+
+    fn visit_u64<E: de::Error>(self, units: u64) -> Result<Money, E> {
+        i64::try_from(units)
+            .ok()
+            .and_then(|units| units.checked_mul(SCALE))
+            .map(Money)
+            .ok_or_else(|| E::custom(format!("{} is out of range", units)))
+    }
+
+    fn visit_f64<E: de::Error>(self, amount: f64) -> Result<Money, E> {
+        if !amount.is_finite() {
+            return Err(E::custom(format!("{} is not an amount of money", amount)));
+        }
+        // Saturates, so the Money::MAX placeholder survives the trip
+        Ok(Money::from_f64(amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(money: Money) -> String {
+        serde_json::to_string(&money).unwrap()
+    }
+
+    fn parse(json: &str) -> Money {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        for micros in [
+            0,
+            1,
+            100_000,
+            123_456_789,
+            -100_000,
+            -123_456_789,
+            999_999_999_999,
+        ] {
+            let money = Money::from_micros(micros);
+            assert_eq!(parse(&json(money)), money, "{}", json(money));
+        }
+
+        assert_eq!(json(Money::from_micros(100_000)), "0.1");
+        assert_eq!(json(Money::from_micros(123_456_789)), "123.456789");
+        assert_eq!(json(Money::from_micros(-2_500_000)), "-2.5");
+    }
+
+    #[test]
+    fn reads_whole_numbers() {
+        assert_eq!(parse("42"), Money::from_micros(42_000_000));
+        assert_eq!(parse("-3"), Money::from_micros(-3_000_000));
+    }
+
+    #[test]
+    fn rounds_extra_digits_when_reading() {
+        assert_eq!(parse("0.0000004"), Money::ZERO);
+        assert_eq!(parse("0.0000006"), Money::from_micros(1));
+        assert_eq!(parse("1.9999999"), Money::from_micros(2_000_000));
+        assert_eq!(parse("-1.9999999"), Money::from_micros(-2_000_000));
+    }
+
+    #[test]
+    fn rejects_what_isnt_money() {
+        assert!(serde_json::from_str::<Money>("\"ten\"").is_err());
+        assert!(serde_json::from_str::<Money>("18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn displays_without_trailing_zeros() {
+        assert_eq!(Money::from_micros(10_000_000).to_string(), "10");
+        assert_eq!(Money::from_micros(10_500_000).to_string(), "10.5");
+        assert_eq!(Money::from_micros(1).to_string(), "0.000001");
+        assert_eq!(Money::from_micros(-500_000).to_string(), "-0.5");
+        assert_eq!(format!("{:.2}", Money::from_micros(1_005_000)), "1.00");
+        assert_eq!(format!("{:>6}", Money::from_micros(2_500_000)), "   2.5");
+        assert_eq!(format!("{:<6}|", Money::from_micros(2_500_000)), "2.5   |");
+    }
+
+    #[test]
+    fn divides_by_counts_to_the_nearest_millionth() {
+        assert_eq!(Money::from_micros(10) / 4, Money::from_micros(3));
+        assert_eq!(Money::from_micros(10) / 3, Money::from_micros(3));
+        assert_eq!(Money::from_micros(11) / 2, Money::from_micros(6));
+        assert_eq!(Money::from_micros(-11) / 2, Money::from_micros(-6));
+        assert_eq!(
+            Money::from_micros(10_000_000) / 3,
+            Money::from_micros(3_333_333)
+        );
+        assert_eq!(Money::from_micros(10) / 0, Money::ZERO);
+    }
+
+    #[test]
+    fn saturates_instead_of_overflowing() {
+        let almost_max = Money::from_micros(i64::MAX - 1);
+
+        assert_eq!(almost_max + Money::from_micros(10), Money::MAX);
+        assert_eq!(Money::MIN - Money::from_micros(10), Money::MIN);
+        assert_eq!(almost_max * 2usize, Money::MAX);
+        assert_eq!(almost_max * 2.0, Money::MAX);
+        assert_eq!(-Money::MIN, Money::MAX);
+        assert_eq!(Money::MAX - Money::MAX, Money::ZERO);
+    }
+
+    #[test]
+    fn keeps_the_max_placeholder_through_json() {
+        assert_eq!(parse(&json(Money::MAX)), Money::MAX);
+    }
+
+    #[test]
+    fn sums_exactly() {
+        let total: Money = [Money::from_micros(100_000); 10].iter().sum();
+
+        assert_eq!(total, Money::from_micros(1_000_000));
+    }
+}
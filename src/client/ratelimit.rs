@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    // How many requests may be sent at once after a quiet period
+    pub burst: f64,
+}
+
+impl RateLimit {
+    pub fn per_second(requests_per_second: f64) -> Self {
+        Self {
+            requests_per_second,
+            burst: requests_per_second.max(1.0),
+        }
+    }
+}
+
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.requests_per_second).min(self.limit.burst);
+        self.last_refill = now;
+    }
+
+    // Time until the next token is available
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 || self.limit.requests_per_second <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - self.tokens) / self.limit.requests_per_second)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitStats {
+    pub requests: u64,
+    pub waits: u64,
+    pub total_wait: Duration,
+}
+
+struct Buckets {
+    global: Option<TokenBucket>,
+    per_endpoint: HashMap<String, TokenBucket>,
+    // Keyed by endpoint, like the buckets
+    stats: HashMap<String, RateLimitStats>,
+}
+
+// Token buckets for all requests together and for single endpoints.
+// A request has to get a token from both before it is sent.
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+// We got throttled by the server while polling the listings every 100ms
+pub const DEFAULT_REQUESTS_PER_SECOND: f64 = 10.0;
+pub const DEFAULT_ENDPOINT_LIMITS: [(&str, f64); 1] = [("/listing", 2.0)];
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        DEFAULT_ENDPOINT_LIMITS.iter().fold(
+            Self::new(Some(RateLimit::per_second(DEFAULT_REQUESTS_PER_SECOND))),
+            |limiter, (endpoint, requests_per_second)| {
+                limiter.with_endpoint_limit(endpoint, RateLimit::per_second(*requests_per_second))
+            },
+        )
+    }
+}
+
+impl RateLimiter {
+    pub fn new(global: Option<RateLimit>) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                global: global.map(TokenBucket::new),
+                per_endpoint: HashMap::new(),
+                stats: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::new(None)
+    }
+
+    // Ids in the endpoint don't matter, "/listing/1" limits all listings like "/listing/{id}"
+    pub fn with_endpoint_limit(self, endpoint: &str, limit: RateLimit) -> Self {
+        self.buckets
+            .lock()
+            .unwrap()
+            .per_endpoint
+            .insert(endpoint_key(endpoint), TokenBucket::new(limit));
+        self
+    }
+
+    // Wait until the request is allowed, returns how long we waited
+    pub async fn acquire(&self, endpoint: &str) -> Duration {
+        let endpoint = endpoint_key(endpoint);
+        let mut waited = Duration::ZERO;
+
+        loop {
+            let wait_time = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let Buckets {
+                    global,
+                    per_endpoint,
+                    stats,
+                } = &mut *buckets;
+
+                let now = Instant::now();
+                let mut wait_time = Duration::ZERO;
+                for bucket in global.iter_mut().chain(per_endpoint.get_mut(&endpoint)) {
+                    bucket.refill(now);
+                    wait_time = wait_time.max(bucket.wait_time());
+                }
+
+                if wait_time.is_zero() {
+                    for bucket in global.iter_mut().chain(per_endpoint.get_mut(&endpoint)) {
+                        bucket.tokens -= 1.0;
+                    }
+
+                    let stats = stats.entry(endpoint).or_default();
+                    stats.requests += 1;
+                    if !waited.is_zero() {
+                        stats.waits += 1;
+                        stats.total_wait += waited;
+                    }
+
+                    return waited;
+                }
+
+                wait_time
+            };
+
+            tokio::time::sleep(wait_time).await;
+            waited += wait_time;
+        }
+    }
+
+    pub fn stats(&self) -> HashMap<String, RateLimitStats> {
+        self.buckets.lock().unwrap().stats.clone()
+    }
+}
+
+// Replace ids in an endpoint with a placeholder, so all listings share one bucket
+pub fn endpoint_key(endpoint: &str) -> String {
+    endpoint
+        .split('/')
+        .map(|segment| {
+            if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) {
+                "{id}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_once_the_burst_is_spent() {
+        let limiter = RateLimiter::new(Some(RateLimit::per_second(2.0)));
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire("/article").await, Duration::ZERO);
+        assert_eq!(limiter.acquire("/tag").await, Duration::ZERO);
+        assert_eq!(
+            limiter.acquire("/article").await,
+            Duration::from_millis(500)
+        );
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        let stats = limiter.stats();
+        assert_eq!(stats["/article"].requests, 2);
+        assert_eq!(stats["/article"].waits, 1);
+        assert_eq!(stats["/article"].total_wait, Duration::from_millis(500));
+        assert_eq!(stats["/tag"].waits, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn refills_while_idle() {
+        let limiter = RateLimiter::new(Some(RateLimit::per_second(1.0)));
+
+        assert_eq!(limiter.acquire("/article").await, Duration::ZERO);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(limiter.acquire("/article").await, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn limits_endpoints_with_and_without_ids_alike() {
+        let limiter = RateLimiter::unlimited()
+            .with_endpoint_limit("/listing/{id}", RateLimit::per_second(1.0));
+
+        assert_eq!(limiter.acquire("/listing/5").await, Duration::ZERO);
+        // Other endpoints don't share the bucket
+        assert_eq!(limiter.acquire("/listing").await, Duration::ZERO);
+        assert_eq!(limiter.acquire("/listing/7").await, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn replaces_ids_in_endpoints() {
+        assert_eq!(
+            endpoint_key("/supplier/3/article/42/buy"),
+            "/supplier/{id}/article/{id}/buy"
+        );
+        assert_eq!(endpoint_key("/listing/new"), "/listing/new");
+    }
+}
@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::StatusCode;
+
+use super::ClientError;
+
+// How often and how patiently we repeat requests that are safe to repeat
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Including the first try, so 1 means no retries at all
+    pub max_attempts: u32,
+    // Delay before the first retry, doubled for every further one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // Share of the delay that is randomized (0.0 to 1.0), so we don't retry in lockstep
+    pub jitter: f64,
+    pub retryable_statuses: Vec<StatusCode>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.5,
+            retryable_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    // Requests that aren't idempotent (buying, creating listings) are only repeated if they
+    // never reached the server, otherwise we might end up buying twice.
+    pub fn is_retryable(&self, error: &ClientError, idempotent: bool) -> bool {
+        match error {
+            ClientError::Transport { source, .. } => idempotent || source.is_connect(),
+            ClientError::Status { status, .. } => {
+                idempotent && self.retryable_statuses.contains(status)
+            }
+            // The server answered, asking again won't change its mind
+            ClientError::Decode { .. } | ClientError::UnexpectedShape { .. } => false,
+            // Replays already contain the retries of the recorded session
+            ClientError::Replayed { .. } | ClientError::Tape { .. } => false,
+        }
+    }
+
+    // Delay before the given retry, starting with 1 for the first retry
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2_u32.saturating_pow(retry.saturating_sub(1)));
+        let delay = exponential.min(self.max_delay);
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_error(status: StatusCode) -> ClientError {
+        ClientError::Status {
+            endpoint: "/listing/new".to_string(),
+            status,
+            body: String::new(),
+        }
+    }
+
+    // Nobody listens on a port we just gave back
+    async fn connect_error() -> ClientError {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let source = reqwest::Client::new()
+            .get(format!("http://{}/article", addr))
+            .send()
+            .await
+            .unwrap_err();
+        ClientError::Transport {
+            endpoint: "/article".to_string(),
+            source,
+        }
+    }
+
+    #[test]
+    fn retries_server_errors_of_idempotent_requests_only() {
+        let policy = RetryPolicy::default();
+        let error = status_error(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert!(policy.is_retryable(&error, true));
+        assert!(!policy.is_retryable(&error, false));
+    }
+
+    #[test]
+    fn doesnt_retry_other_statuses() {
+        let policy = RetryPolicy::default();
+
+        assert!(!policy.is_retryable(&status_error(StatusCode::BAD_REQUEST), true));
+    }
+
+    #[tokio::test]
+    async fn retries_connect_errors_of_all_requests() {
+        let policy = RetryPolicy::default();
+        let error = connect_error().await;
+
+        assert!(policy.is_retryable(&error, true));
+        assert!(policy.is_retryable(&error, false));
+    }
+
+    #[test]
+    fn delay_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(5), Duration::from_millis(1000));
+        assert_eq!(policy.delay(40), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+
+        for _ in 0..100 {
+            let delay = policy.delay(10);
+            assert!(delay >= Duration::from_millis(500));
+            assert!(delay <= Duration::from_millis(1000));
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{types::*, ArticlePriceHistory, FetchReport, MarketIndex, MarketState};
+
+// Everything we know about the market at one point in time. This is what strategies get to see.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketSnapshot {
+    pub tick: u64,
+    // Time since the game started
+    pub elapsed: Duration,
+
+    pub player: Player,
+    pub articles: Vec<Article>,
+    pub tags: Vec<Tag>,
+    pub suppliers: Vec<Supplier>,
+    pub listings: Vec<Listing>,
+    pub article_price_history: HashMap<ArticleId, ArticlePriceHistory>,
+    // The fetch at the start of the tick: when it went out and what didn't come back
+    #[serde(default)]
+    pub fetch: FetchReport,
+    // See MarketState
+    #[serde(default)]
+    pub player_fetches: u64,
+    #[serde(default)]
+    pub listings_fetches: u64,
+
+    // Shared with the state it was captured from, built on first use for recorded snapshots
+    #[serde(skip)]
+    index: OnceLock<MarketIndex>,
+}
+
+impl MarketSnapshot {
+    pub fn capture(state: &MarketState, tick: u64, elapsed: Duration) -> Self {
+        Self {
+            tick,
+            elapsed,
+
+            player: state.player.clone(),
+            articles: state.articles.clone(),
+            tags: state.tags.clone(),
+            suppliers: state.suppliers.clone(),
+            listings: state.listings.clone(),
+            article_price_history: state.article_price_history.clone(),
+            fetch: state.last_fetch.clone(),
+            player_fetches: state.player_fetches,
+            listings_fetches: state.listings_fetches,
+            index: OnceLock::from(state.index().clone()),
+        }
+    }
+
+    pub fn index(&self) -> &MarketIndex {
+        self.index.get_or_init(|| {
+            MarketIndex::build(&self.articles, &self.tags, &self.suppliers, &self.listings)
+        })
+    }
+
+    // Recordings are JSON lines, one snapshot per tick
+    pub fn append_to(&self, path: &Path) -> io::Result<()> {
+        let mut line = serde_json::to_vec(self)?;
+        line.push(b'\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(&line)
+    }
+
+    pub fn load_all(path: &Path) -> io::Result<Vec<Self>> {
+        fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {}", index + 1, e),
+                    )
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::types::*;
+use super::{FetchReport, MarketIndex};
+
+// Our local copy of the marketplace, kept up to date by every MarketApi implementation.
+// Articles, tags, suppliers and listings go through the update methods, so the index follows.
+pub struct MarketState {
+    pub player: Player,
+    pub articles: Vec<Article>,
+    pub article_price_history: HashMap<ArticleId, ArticlePriceHistory>,
+    pub tags: Vec<Tag>,
+    pub suppliers: Vec<Supplier>,
+    pub listings: Vec<Listing>,
+    pub last_fetch: FetchReport,
+    // How often the player and the listings came back from the server. Tells whether two
+    // looks at the state saw the same data.
+    pub player_fetches: u64,
+    pub listings_fetches: u64,
+    index: MarketIndex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArticlePriceHistory {
+    pub supplier_price_history: Vec<Money>,
+}
+
+impl ArticlePriceHistory {
+    pub fn new(supplier_price: Money) -> Self {
+        Self {
+            supplier_price_history: vec![supplier_price],
+        }
+    }
+
+    pub fn average_price(&self) -> Money {
+        let sum: Money = self.supplier_price_history.iter().sum();
+        sum / self.supplier_price_history.len()
+    }
+
+    fn record_supplier_price(&mut self, supplier_price: Money) {
+        if self.supplier_price_history.last() != Some(&supplier_price) {
+            self.supplier_price_history.push(supplier_price);
+        }
+    }
+}
+
+impl Default for MarketState {
+    fn default() -> Self {
+        Self {
+            articles: Vec::new(),
+            article_price_history: HashMap::new(),
+            tags: Vec::new(),
+            suppliers: Vec::new(),
+            player: Player {
+                id: PlayerId(0),
+                money: Money::MAX, // Unrealistic number, so we can initialize cleanly
+                stock: Vec::new(),
+            },
+            listings: Vec::new(),
+            last_fetch: FetchReport::default(),
+            player_fetches: 0,
+            listings_fetches: 0,
+            index: MarketIndex::default(),
+        }
+    }
+}
+
+impl MarketState {
+    pub fn index(&self) -> &MarketIndex {
+        &self.index
+    }
+
+    pub fn update_player(&mut self, latest: Player) {
+        self.player = latest;
+        self.player_fetches += 1;
+    }
+
+    // Only what changed gets indexed again, the catalog rarely does
+
+    pub fn update_articles(&mut self, latest: Vec<Article>) {
+        if self.articles != latest {
+            self.articles = latest;
+            self.index.index_articles(&self.articles);
+        }
+    }
+
+    pub fn update_tags(&mut self, latest: Vec<Tag>) {
+        if self.tags != latest {
+            self.tags = latest;
+            self.index.index_tags(&self.tags);
+        }
+    }
+
+    pub fn update_listings(&mut self, latest: Vec<Listing>) {
+        self.listings_fetches += 1;
+        if self.listings != latest {
+            self.listings = latest;
+            self.index.index_listings(&self.listings);
+        }
+    }
+
+    pub fn update_suppliers(&mut self, latest: Vec<Supplier>) {
+        if self.suppliers != latest {
+            // Supplier-Stock changes, save potential article price changes
+            // Due to a bug that won't be fixed, all suppliers of our server have the same price for
+            // an article. Other servers may differ, we buy from the cheapest, so its price is kept.
+            let mut cheapest_prices: HashMap<ArticleId, Money> = HashMap::new();
+            for stock in latest.iter().flat_map(|supplier| supplier.stock.iter()) {
+                cheapest_prices
+                    .entry(stock.article_id)
+                    .and_modify(|price| *price = (*price).min(stock.price))
+                    .or_insert(stock.price);
+            }
+            cheapest_prices.into_iter().for_each(|(article_id, price)| {
+                self.article_price_history
+                    .entry(article_id)
+                    .and_modify(|article_price_history| {
+                        article_price_history.record_supplier_price(price)
+                    })
+                    .or_insert_with(|| ArticlePriceHistory::new(price));
+            });
+
+            self.suppliers = latest;
+            self.index.index_suppliers(&self.suppliers);
+        }
+    }
+
+    // Put back the history of a previous run, the prices we fetched since come on top
+    pub fn restore_price_history(&mut self, saved: HashMap<ArticleId, ArticlePriceHistory>) {
+        for (article_id, mut history) in saved {
+            if let Some(current_price) = self
+                .article_price_history
+                .get(&article_id)
+                .and_then(|current| current.supplier_price_history.last())
+            {
+                history.record_supplier_price(*current_price);
+            }
+            self.article_price_history.insert(article_id, history);
+        }
+    }
+}
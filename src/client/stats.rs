@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::endpoint_key;
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestStats {
+    // Every attempt counts, retries included
+    pub requests: u64,
+    pub errors: u64,
+    // From sending the request until the whole response was read, without rate limiter waits
+    pub total_latency: Duration,
+}
+
+// How the server treated our requests, per endpoint (ids collapsed like for the rate limiter)
+#[derive(Default)]
+pub struct RequestLog {
+    stats: Mutex<HashMap<String, RequestStats>>,
+}
+
+impl RequestLog {
+    pub fn record(&self, endpoint: &str, latency: Duration, ok: bool) {
+        let mut stats = self.stats.lock().unwrap();
+        let stats = stats.entry(endpoint_key(endpoint)).or_default();
+        stats.requests += 1;
+        if !ok {
+            stats.errors += 1;
+        }
+        stats.total_latency += latency;
+    }
+
+    pub fn stats(&self) -> HashMap<String, RequestStats> {
+        self.stats.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::{ClientError, MarketPart};
+
+// Client::fetch_market asks for every part of the market at once
+const MAX_CONCURRENT_READS: usize = MarketPart::ALL.len();
+
+// One API exchange, as the game saw it after all retries. A tape is a JSONL file of these.
+// Attempts that failed and were retried are left off on purpose: a replay stands in for
+// Client::send as a whole, so only the result the game got back is needed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TapeEntry {
+    // Microseconds since the unix epoch when the exchange was over
+    pub timestamp: u64,
+    pub method: String,
+    pub endpoint: String,
+    pub body: Option<serde_json::Value>,
+    // None if the request never got an answer
+    pub status: Option<u16>,
+    // The response JSON. Responses that aren't JSON (and failures without a response) are
+    // kept as a string.
+    pub response: serde_json::Value,
+}
+
+impl TapeEntry {
+    pub fn new(
+        method: String,
+        endpoint: &str,
+        body: Option<serde_json::Value>,
+        result: &Result<String, ClientError>,
+    ) -> Self {
+        let (status, response) = match result {
+            Ok(response) => (Some(StatusCode::OK.as_u16()), response_json(response)),
+            Err(ClientError::Status { status, body, .. }) => {
+                (Some(status.as_u16()), response_json(body))
+            }
+            Err(e) => (None, serde_json::Value::String(e.to_string())),
+        };
+
+        Self {
+            timestamp: unix_time_in_us(),
+            method,
+            endpoint: endpoint.to_string(),
+            body,
+            status,
+            response,
+        }
+    }
+
+    // Turn the recording back into what Client::send returned back then
+    fn result(self) -> Result<String, ClientError> {
+        let body = match self.response {
+            serde_json::Value::String(text) => text,
+            json => json.to_string(),
+        };
+
+        match self.status.map(StatusCode::from_u16) {
+            Some(Ok(StatusCode::OK)) => Ok(body),
+            Some(Ok(status)) => Err(ClientError::Status {
+                endpoint: self.endpoint,
+                status,
+                body,
+            }),
+            Some(Err(_)) => Err(ClientError::Tape {
+                endpoint: self.endpoint,
+                reason: "Invalid status on tape".to_string(),
+            }),
+            None => Err(ClientError::Replayed {
+                endpoint: self.endpoint,
+                reason: body,
+            }),
+        }
+    }
+}
+
+fn response_json(response: &str) -> serde_json::Value {
+    serde_json::from_str(response)
+        .unwrap_or_else(|_| serde_json::Value::String(response.to_string()))
+}
+
+fn unix_time_in_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64)
+}
+
+// Appends every exchange to a tape
+pub struct TapeRecorder {
+    file: Mutex<File>,
+}
+
+impl TapeRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, entry: &TapeEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        file.write_all(&line)
+    }
+}
+
+// Serves the exchanges of a tape in order. Time is taken from the tape as well, so the
+// game makes the same decisions as in the recorded session.
+pub struct TapePlayer {
+    entries: Mutex<VecDeque<TapeEntry>>,
+    started: Instant,
+    first_timestamp: u64,
+    last_timestamp: Mutex<u64>,
+}
+
+impl TapePlayer {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let entries = fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str::<TapeEntry>(line).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: {}", index + 1, e),
+                    )
+                })
+            })
+            .collect::<io::Result<VecDeque<_>>>()?;
+
+        let first_timestamp = entries.front().map_or(0, |entry| entry.timestamp);
+
+        Ok(Self {
+            entries: Mutex::new(entries),
+            started: Instant::now(),
+            first_timestamp,
+            last_timestamp: Mutex::new(first_timestamp),
+        })
+    }
+
+    pub fn replay(&self, method: &str, endpoint: &str) -> Result<String, ClientError> {
+        let mut entries = self.entries.lock().unwrap();
+
+        // Concurrent reads are on the tape in the order they finished, not in the order we ask
+        let position = if method == "GET" {
+            entries
+                .iter()
+                .take(MAX_CONCURRENT_READS)
+                .take_while(|entry| entry.method == method)
+                .position(|entry| entry.endpoint == endpoint)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let entry = match entries.remove(position) {
+            Some(entry) => entry,
+            None => {
+                return Err(ClientError::Tape {
+                    endpoint: endpoint.to_string(),
+                    reason: "End of tape".to_string(),
+                })
+            }
+        };
+        drop(entries);
+
+        // A different request means the session took another path, nothing after this fits
+        if entry.method != method || entry.endpoint != endpoint {
+            return Err(ClientError::Tape {
+                endpoint: endpoint.to_string(),
+                reason: format!(
+                    "Expected {} {}, the tape has {} {}",
+                    method, endpoint, entry.method, entry.endpoint
+                ),
+            });
+        }
+
+        let mut last_timestamp = self.last_timestamp.lock().unwrap();
+        *last_timestamp = (*last_timestamp).max(entry.timestamp);
+        entry.result()
+    }
+
+    // The time of the last replayed exchange, as if the session was running right now
+    pub fn now(&self) -> Instant {
+        let offset = self
+            .last_timestamp
+            .lock()
+            .unwrap()
+            .saturating_sub(self.first_timestamp);
+        self.started + Duration::from_micros(offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Exchanges one second apart, recorded and loaded back
+    fn tape(exchanges: Vec<(&str, &str, Result<String, ClientError>)>) -> TapePlayer {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tape.jsonl");
+        let recorder = TapeRecorder::create(&path).unwrap();
+        for (second, (method, endpoint, result)) in exchanges.into_iter().enumerate() {
+            let mut entry = TapeEntry::new(method.to_string(), endpoint, None, &result);
+            entry.timestamp = (second as u64 + 1) * 1_000_000;
+            recorder.record(&entry).unwrap();
+        }
+
+        TapePlayer::load(&path).unwrap()
+    }
+
+    fn ok(response: &str) -> Result<String, ClientError> {
+        Ok(response.to_string())
+    }
+
+    fn tape_error(result: Result<String, ClientError>) -> String {
+        match result {
+            Err(ClientError::Tape { reason, .. }) => reason,
+            other => panic!("expected a tape error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn replays_concurrent_reads_in_any_order() {
+        let player = tape(vec![
+            ("GET", "/articles", ok("[1]")),
+            ("GET", "/tags", ok("[2]")),
+            ("GET", "/player", ok("[3]")),
+        ]);
+
+        assert_eq!(player.replay("GET", "/player").unwrap(), "[3]");
+        assert_eq!(player.replay("GET", "/articles").unwrap(), "[1]");
+        assert_eq!(player.replay("GET", "/tags").unwrap(), "[2]");
+    }
+
+    #[test]
+    fn doesnt_reorder_reads_across_writes() {
+        let player = tape(vec![
+            ("GET", "/articles", ok("[1]")),
+            ("POST", "/listing", ok("{\"id\":1}")),
+            ("GET", "/tags", ok("[2]")),
+        ]);
+
+        // The read after the write hasn't happened yet
+        assert_eq!(
+            tape_error(player.replay("GET", "/tags")),
+            "Expected GET /tags, the tape has GET /articles"
+        );
+        assert_eq!(player.replay("POST", "/listing").unwrap(), "{\"id\":1}");
+        assert_eq!(player.replay("GET", "/tags").unwrap(), "[2]");
+    }
+
+    #[test]
+    fn rejects_another_request() {
+        let player = tape(vec![("POST", "/listing", ok("{}"))]);
+
+        assert_eq!(
+            tape_error(player.replay("PUT", "/listing")),
+            "Expected PUT /listing, the tape has POST /listing"
+        );
+    }
+
+    #[test]
+    fn replays_failures() {
+        let player = tape(vec![
+            (
+                "GET",
+                "/player",
+                Err(ClientError::Status {
+                    endpoint: "/player".to_string(),
+                    status: StatusCode::SERVICE_UNAVAILABLE,
+                    body: "{\"error\":\"busy\"}".to_string(),
+                }),
+            ),
+            // Any error without a response is recorded like a transport error
+            (
+                "GET",
+                "/tags",
+                Err(ClientError::Tape {
+                    endpoint: "/tags".to_string(),
+                    reason: "connection refused".to_string(),
+                }),
+            ),
+        ]);
+
+        match player.replay("GET", "/player") {
+            Err(ClientError::Status {
+                endpoint,
+                status,
+                body,
+            }) => {
+                assert_eq!(endpoint, "/player");
+                assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+                assert_eq!(body, "{\"error\":\"busy\"}");
+            }
+            other => panic!("expected a status error, got {:?}", other),
+        }
+        match player.replay("GET", "/tags") {
+            Err(ClientError::Replayed { reason, .. }) => assert!(reason.contains("refused")),
+            other => panic!("expected a replayed error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ends_with_the_tape() {
+        let player = tape(vec![("GET", "/tags", ok("[]"))]);
+        player.replay("GET", "/tags").unwrap();
+
+        assert_eq!(tape_error(player.replay("GET", "/tags")), "End of tape");
+    }
+
+    #[test]
+    fn keeps_the_time_of_the_tape() {
+        let player = tape(vec![
+            ("GET", "/tags", ok("[]")),
+            ("GET", "/articles", ok("[]")),
+            ("POST", "/listing", ok("{}")),
+        ]);
+        let start = player.now();
+
+        // Out of order reads don't turn back the clock
+        player.replay("GET", "/articles").unwrap();
+        assert_eq!(player.now() - start, Duration::from_secs(1));
+        player.replay("GET", "/tags").unwrap();
+        assert_eq!(player.now() - start, Duration::from_secs(1));
+        player.replay("POST", "/listing").unwrap();
+        assert_eq!(player.now() - start, Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::client::types::{ArticleId, Listing, Money};
+use crate::client::MarketSnapshot;
+
+use super::config::BanditConfig;
+use super::reconcile::Sale;
+use super::strategy::{Action, Strategy};
+
+// One price multiplier of an article and what it brought so far
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Arm {
+    pulls: u64,
+    // Profit per tick in units of the average supplier price, so arms of cheap and expensive
+    // articles learn alike
+    total_reward: f64,
+}
+
+impl Arm {
+    fn mean(&self) -> f64 {
+        self.total_reward / self.pulls as f64
+    }
+}
+
+// Prices every article at one of a few multiples of its average supplier price and learns
+// which one brings the most profit per tick (UCB1). Every tick is one pull of the arm the
+// article was priced at. Exploration fades over the game, so late in the game we mostly
+// stick to what worked.
+//
+// Buying is kept simple: keep a few units of every article on sale.
+pub struct BanditStrategy {
+    config: BanditConfig,
+    state: BanditState,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct BanditState {
+    // One arm per multiplier in the config
+    arms: HashMap<ArticleId, Vec<Arm>>,
+    // The arm an article is priced at since the last tick, with the units sold since
+    pulled: HashMap<ArticleId, (usize, usize)>,
+}
+
+impl Default for BanditStrategy {
+    fn default() -> Self {
+        Self::new(BanditConfig::default())
+    }
+}
+
+impl BanditStrategy {
+    pub fn new(config: BanditConfig) -> Self {
+        Self {
+            config,
+            state: BanditState::default(),
+        }
+    }
+
+    // The pulls since the last tick are over, reward them by what sold
+    fn reward(&mut self) {
+        let multipliers = &self.config.multipliers;
+        for (article_id, (arm, sold)) in self.state.pulled.drain() {
+            let arms = self
+                .state
+                .arms
+                .entry(article_id)
+                .or_insert_with(|| vec![Arm::default(); multipliers.len()]);
+            if let Some(arm_stats) = arms.get_mut(arm) {
+                arm_stats.pulls += 1;
+                arm_stats.total_reward += sold as f64 * (multipliers[arm] - 1.0);
+            }
+        }
+    }
+
+    // We don't reprice in ticks we sit out, so the pulls go on for another tick
+    fn carry_over(&mut self) {
+        let pulled = self
+            .state
+            .pulled
+            .iter()
+            .map(|(article_id, (arm, _))| (*article_id, (*arm, 0)))
+            .collect();
+        self.reward();
+        self.state.pulled = pulled;
+    }
+
+    fn exploration(&self, snapshot: &MarketSnapshot) -> f64 {
+        let half_lives =
+            snapshot.elapsed.as_secs_f64() / self.config.exploration_half_life_seconds as f64;
+        self.config.exploration * 0.5f64.powf(half_lives)
+    }
+
+    // Arms we never tried come first, then the best mean plus a bonus for arms we know little
+    // about. Ties go to the lower multiplier.
+    fn choose(&self, article_id: ArticleId, exploration: f64) -> usize {
+        let arms = match self.state.arms.get(&article_id) {
+            Some(arms) if arms.len() == self.config.multipliers.len() => arms,
+            _ => return 0,
+        };
+        if let Some(untried) = arms.iter().position(|arm| arm.pulls == 0) {
+            return untried;
+        }
+
+        let total_pulls = arms.iter().map(|arm| arm.pulls).sum::<u64>() as f64;
+        let score = |arm: &Arm| {
+            arm.mean() + exploration * (2.0 * total_pulls.ln() / arm.pulls as f64).sqrt()
+        };
+        let mut best = 0;
+        for (index, arm) in arms.iter().enumerate().skip(1) {
+            if score(arm) > score(&arms[best]) {
+                best = index;
+            }
+        }
+        best
+    }
+
+    // The arm the article is priced at until the next tick
+    fn pull(&mut self, article_id: ArticleId, exploration: f64) -> usize {
+        if let Some((arm, _)) = self.state.pulled.get(&article_id) {
+            return *arm;
+        }
+        let arm = self.choose(article_id, exploration);
+        self.state.pulled.insert(article_id, (arm, 0));
+        arm
+    }
+
+    fn price(&self, snapshot: &MarketSnapshot, article_id: ArticleId, arm: usize) -> Option<Money> {
+        let history = snapshot.article_price_history.get(&article_id)?;
+        Some(history.average_price() * self.config.multipliers[arm])
+    }
+
+    // Keep stock_per_article units of every article, listed or in stock
+    fn shop(&self, snapshot: &MarketSnapshot, own_listings: &[Listing]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let mut available_money = snapshot.player.money - self.config.reserve;
+
+        for article in &snapshot.articles {
+            let listed = own_listings
+                .iter()
+                .filter(|listing| listing.article == article.id)
+                .map(|listing| listing.count)
+                .sum::<usize>();
+            let in_stock = snapshot
+                .player
+                .stock
+                .iter()
+                .filter(|stock| stock.article_id == article.id)
+                .map(|stock| stock.stock)
+                .sum::<usize>();
+            let mut count = self
+                .config
+                .stock_per_article
+                .saturating_sub(listed + in_stock);
+            let supplier_stocks = snapshot.suppliers_by_price(article.id);
+            if count == 0 || supplier_stocks.is_empty() {
+                continue;
+            }
+
+            let article_average_price = match snapshot.article_price_history.get(&article.id) {
+                Some(history) => history.average_price(),
+                None => {
+                    warn!(article_id = %article.id, "Weird. Didn't find an article price history");
+                    continue;
+                }
+            };
+            let max_price = article_average_price * self.config.average_price_buying_multiplier;
+
+            // Cheapest first, once one is too expensive all others are as well
+            for (supplier_id, stock) in supplier_stocks {
+                if stock.price > max_price {
+                    break;
+                }
+                if !stock.price.is_positive() {
+                    continue;
+                }
+
+                let affordable = (available_money.as_micros() / stock.price.as_micros()).max(0);
+                let amount_to_buy = count
+                    .min(stock.stock)
+                    .min(usize::try_from(affordable).unwrap_or(usize::MAX));
+                if amount_to_buy == 0 {
+                    continue;
+                }
+
+                actions.push(Action::Buy {
+                    supplier_id,
+                    article_id: article.id,
+                    count: amount_to_buy,
+                    price_per_unit: stock.price,
+                });
+                // We don't know yet if the buy works out, assume it does
+                available_money -= stock.price * amount_to_buy;
+                count -= amount_to_buy;
+                if count == 0 {
+                    break;
+                }
+            }
+        }
+
+        actions
+    }
+}
+
+impl Strategy for BanditStrategy {
+    fn name(&self) -> &str {
+        "bandit"
+    }
+
+    fn on_tick(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        // Stale listings would look like nothing sold at the new prices
+        if !snapshot.fetch.is_complete() {
+            info!("Market data incomplete, sitting this tick out");
+            self.carry_over();
+            return Vec::new();
+        }
+
+        self.reward();
+
+        let exploration = self.exploration(snapshot);
+        let own_listings = snapshot.get_own_listings(&[]);
+        let mut actions = Vec::new();
+
+        // Pull an arm for every article we have on sale
+        for listing in own_listings.iter().filter(|listing| listing.count > 0) {
+            let arm = self.pull(listing.article, exploration);
+            let price = match self.price(snapshot, listing.article, arm) {
+                Some(price) => price,
+                None => {
+                    warn!(article_id = %listing.article, "Weird. Didn't find an article price history");
+                    continue;
+                }
+            };
+            debug!(
+                article_id = %listing.article,
+                multiplier = self.config.multipliers[arm],
+                price = %price,
+                exploration,
+                "Price arm"
+            );
+
+            if price != listing.price {
+                actions.push(Action::UpdateListing {
+                    listing_id: listing.id,
+                    count: listing.count,
+                    price_per_unit: price,
+                });
+            }
+        }
+
+        actions.extend(self.shop(snapshot, &own_listings));
+        actions
+    }
+
+    fn on_restock(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        let exploration = self.exploration(snapshot);
+        let mut actions = Vec::new();
+
+        // Move whole stock to listings, at the price of the article's arm
+        for stock in &snapshot.player.stock {
+            let arm = self.pull(stock.article_id, exploration);
+            let price = match self.price(snapshot, stock.article_id, arm) {
+                Some(price) => price,
+                None => {
+                    warn!(article_id = %stock.article_id, "Weird. Didn't find an article price history");
+                    continue;
+                }
+            };
+
+            let listing = snapshot
+                .listings_of_player(snapshot.player.id)
+                .find(|listing| listing.article == stock.article_id);
+            actions.push(match listing {
+                Some(listing) => Action::UpdateListing {
+                    listing_id: listing.id,
+                    count: listing.count + stock.stock,
+                    price_per_unit: price,
+                },
+                None => Action::CreateListing {
+                    article_id: stock.article_id,
+                    count: stock.stock,
+                    price_per_unit: price,
+                },
+            });
+        }
+
+        actions
+    }
+
+    fn on_sales(&mut self, sales: &[Sale]) {
+        for sale in sales {
+            // Sales before the article got an arm don't tell us anything about one
+            if let Some((_, sold)) = self.state.pulled.get_mut(&sale.article_id) {
+                *sold += sale.count;
+            }
+        }
+    }
+
+    fn save_state(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(&self.state).ok()
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> Result<(), serde_json::Error> {
+        let mut state: BanditState = serde_json::from_value(state)?;
+        // Arms only make sense with the multipliers they were learned with
+        let arm_count = self.config.multipliers.len();
+        state.arms.retain(|_, arms| arms.len() == arm_count);
+        state.pulled.retain(|_, (arm, _)| *arm < arm_count);
+
+        self.state = state;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::client::memory::{MemoryClient, MemoryMarket};
+    use crate::client::{FailedFetch, MarketApi, MarketPart};
+    use crate::game::testing::{TestMarket, PLAYER};
+    use crate::game::{self, Bookkeeper, Shutdown};
+    use crate::metrics::Metrics;
+    use crate::simulator::customers::CustomerModel;
+
+    fn config() -> BanditConfig {
+        BanditConfig {
+            multipliers: vec![1.1, 1.5, 3.0],
+            exploration_half_life_seconds: 60,
+            reserve: Money::ZERO,
+            ..BanditConfig::default()
+        }
+    }
+
+    fn market() -> TestMarket {
+        TestMarket::default()
+            .article(1, &[], 10.0)
+            .article(2, &[], 20.0)
+            .article(3, &[], 30.0)
+    }
+
+    fn sale(article_id: usize, count: usize) -> Sale {
+        Sale {
+            listing_id: crate::client::types::ListingId(article_id),
+            article_id: ArticleId(article_id),
+            count,
+            unit_price: Money::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn learns_from_customers() {
+        let market = market();
+        let mut memory_market = MemoryMarket::new(market.articles, market.tags, market.suppliers);
+        assert_eq!(memory_market.add_player(Money::from_f64(1000.0)), PLAYER);
+        let mut client = MemoryClient::new(memory_market, PLAYER).unwrap();
+
+        let mut strategy = BanditStrategy::new(config());
+        let metrics = Metrics::default();
+        let mut bookkeeper = Bookkeeper::new(&mut strategy, &metrics);
+        let customers = CustomerModel::default();
+        let mut rng = StdRng::seed_from_u64(7);
+
+        for tick in 1..=10 {
+            client.fetch_market().await.unwrap();
+            let elapsed = Duration::from_secs(30 * tick);
+            game::play_tick(
+                &mut client,
+                &mut bookkeeper,
+                tick,
+                elapsed,
+                &Shutdown::never(),
+            )
+            .await
+            .unwrap();
+            customers.serve(&mut client.market, &mut rng);
+        }
+        drop(bookkeeper);
+
+        // Every article got to try every price and something sold
+        assert_eq!(strategy.state.arms.len(), 3);
+        for arms in strategy.state.arms.values() {
+            assert!(arms.iter().all(|arm| arm.pulls > 0));
+        }
+        let total_reward = strategy
+            .state
+            .arms
+            .values()
+            .flatten()
+            .map(|arm| arm.total_reward)
+            .sum::<f64>();
+        assert!(total_reward > 0.0);
+        assert!(client.market.player(PLAYER).unwrap().money > Money::from_f64(1000.0) * 0.5);
+    }
+
+    #[test]
+    fn exploration_fades_over_the_game() {
+        let strategy = BanditStrategy::new(config());
+        let mut snapshot = market().snapshot();
+
+        let start = strategy.exploration(&snapshot);
+        snapshot.elapsed = Duration::from_secs(60);
+        let one_half_life = strategy.exploration(&snapshot);
+        snapshot.elapsed = Duration::from_secs(60 * 60);
+        let much_later = strategy.exploration(&snapshot);
+
+        assert_eq!(start, 5.0);
+        assert!((one_half_life - 2.5).abs() < 1e-9);
+        assert!(much_later < 1e-9);
+    }
+
+    #[test]
+    fn sticks_to_the_best_arm_once_exploration_is_gone() {
+        let mut strategy = BanditStrategy::new(config());
+        strategy.state.arms.insert(
+            ArticleId(1),
+            vec![
+                Arm {
+                    pulls: 10,
+                    total_reward: 1.0,
+                },
+                Arm {
+                    pulls: 10,
+                    total_reward: 5.0,
+                },
+                Arm {
+                    pulls: 1,
+                    total_reward: 0.0,
+                },
+            ],
+        );
+
+        assert_eq!(strategy.choose(ArticleId(1), 0.0), 1);
+        // The arm we know least about gets a chance while exploring
+        assert_eq!(strategy.choose(ArticleId(1), 5.0), 2);
+        // Untried arms come first
+        assert_eq!(strategy.choose(ArticleId(2), 0.0), 0);
+    }
+
+    #[test]
+    fn keeps_pulling_through_ticks_without_market_data() {
+        let mut strategy = BanditStrategy::new(config());
+        strategy.state.pulled.insert(ArticleId(1), (2, 0));
+        strategy.on_sales(&[sale(1, 3)]);
+
+        let mut snapshot = market().snapshot();
+        snapshot.fetch.failures.push(FailedFetch {
+            part: MarketPart::Listings,
+            reason: "timeout".to_string(),
+        });
+        assert!(strategy.on_tick(&snapshot).is_empty());
+
+        // The sales so far are rewarded, the arm stays pulled for what sells next
+        let arm = strategy.state.arms[&ArticleId(1)][2];
+        assert_eq!(arm.pulls, 1);
+        assert!((arm.total_reward - 6.0).abs() < 1e-9);
+        assert_eq!(strategy.state.pulled[&ArticleId(1)], (2, 0));
+
+        strategy.on_sales(&[sale(1, 1)]);
+        assert_eq!(strategy.state.pulled[&ArticleId(1)], (2, 1));
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use tracing::info;
+
+use crate::client::types::{Money, TagId};
+use crate::client::{ClientError, MarketSnapshot};
+use crate::metrics::Metrics;
+
+use super::ledger::Ledger;
+use super::reconcile::{Reconciler, Sale};
+use super::strategy::{Action, ActionOutcome, Strategy};
+
+// Passes everything through to the strategy, books our trades in the ledger and keeps the
+// metrics up to date on the way. Sales are found on every look at the market and handed to the
+// strategy before it gets to see the market.
+pub struct Bookkeeper<'a, S: ?Sized> {
+    strategy: &'a mut S,
+    metrics: &'a Metrics,
+    pub ledger: Ledger,
+    pub reconciler: Reconciler,
+}
+
+impl<'a, S: Strategy + ?Sized> Bookkeeper<'a, S> {
+    pub fn new(strategy: &'a mut S, metrics: &'a Metrics) -> Self {
+        Self {
+            strategy,
+            metrics,
+            ledger: Ledger::default(),
+            reconciler: Reconciler::default(),
+        }
+    }
+
+    fn reconcile(&mut self, snapshot: &MarketSnapshot) {
+        let sales = self.reconciler.observe(snapshot);
+        if sales.is_empty() {
+            return;
+        }
+
+        for sale in &sales {
+            self.ledger.record_sale(sale);
+            self.metrics.add_units_sold(sale.article_id, sale.count);
+        }
+        self.strategy.on_sales(&sales);
+    }
+}
+
+impl<S: Strategy + ?Sized> Strategy for Bookkeeper<'_, S> {
+    fn name(&self) -> &str {
+        self.strategy.name()
+    }
+
+    fn on_start(&mut self, snapshot: &MarketSnapshot) {
+        self.strategy.on_start(snapshot)
+    }
+
+    fn on_tick(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        self.reconcile(snapshot);
+        self.strategy.on_tick(snapshot)
+    }
+
+    fn on_restock(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        self.reconcile(snapshot);
+        self.strategy.on_restock(snapshot)
+    }
+
+    fn on_tick_end(&mut self, snapshot: &MarketSnapshot) {
+        self.reconcile(snapshot);
+        self.strategy.on_tick_end(snapshot);
+
+        let own_listings = snapshot
+            .listings_of_player(snapshot.player.id)
+            .cloned()
+            .collect::<Vec<_>>();
+        self.metrics
+            .set_player(snapshot.player.money, self.strategy.piggybank());
+        self.metrics
+            .set_portfolio(snapshot.portfolio(&own_listings));
+        let realized = self.ledger.realized();
+        let unrealized = self.ledger.total_unrealized(snapshot);
+        info!(realized = %realized, unrealized = %unrealized, "Profit and loss");
+        self.metrics.set_pnl(realized, unrealized);
+        if let Some(tag_trends) = self.strategy.tag_trends() {
+            self.metrics.set_tag_trends(tag_trends);
+        }
+    }
+
+    fn on_watch(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        self.reconcile(snapshot);
+        self.strategy.on_watch(snapshot)
+    }
+
+    fn on_outcome(&mut self, action: &Action, outcome: &Result<ActionOutcome, ClientError>) {
+        self.reconciler.record(action, outcome);
+        if let (
+            Action::Buy {
+                supplier_id,
+                article_id,
+                count,
+                price_per_unit,
+            },
+            Ok(_),
+        ) = (action, outcome)
+        {
+            info!(
+                %supplier_id,
+                %article_id,
+                count,
+                price = %price_per_unit,
+                "Bought"
+            );
+            self.ledger
+                .record_buy(*supplier_id, *article_id, *count, *price_per_unit);
+            self.metrics.add_units_bought(*supplier_id, *count);
+        }
+        self.strategy.on_outcome(action, outcome)
+    }
+
+    fn on_sales(&mut self, sales: &[Sale]) {
+        self.strategy.on_sales(sales)
+    }
+
+    fn save_state(&self) -> Option<serde_json::Value> {
+        self.strategy.save_state()
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> Result<(), serde_json::Error> {
+        self.strategy.restore_state(state)
+    }
+
+    fn piggybank(&self) -> Option<Money> {
+        self.strategy.piggybank()
+    }
+
+    fn tag_trends(&self) -> Option<BTreeMap<TagId, f64>> {
+        self.strategy.tag_trends()
+    }
+}
@@ -0,0 +1,206 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::client::types::{ArticleId, Money, SupplierId};
+
+// Units of an article we would like to have, and what we expect to sell one for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wanted {
+    pub article_id: ArticleId,
+    pub count: usize,
+    pub expected_price: Money,
+}
+
+// What a supplier has of an article
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Offer {
+    pub supplier_id: SupplierId,
+    pub article_id: ArticleId,
+    pub available: usize,
+    pub unit_price: Money,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Purchase {
+    pub supplier_id: SupplierId,
+    pub article_id: ArticleId,
+    pub count: usize,
+    pub unit_price: Money,
+}
+
+impl Purchase {
+    pub fn cost(&self) -> Money {
+        self.unit_price * self.count
+    }
+}
+
+// Spend the budget on the units with the most expected margin per money spent, no more than
+// wanted of an article and available from a supplier. Units without a margin are left alone.
+//
+// This is a knapsack. Taking offers by margin per money is the optimum of its LP relaxation,
+// rounded down to whole units here. The money left over goes to the next best units that
+// still fit, so a pricey unit we can't afford doesn't stop us from buying cheaper ones.
+// Ties go to the article we want more of, then the lower article id and supplier id.
+pub fn plan_purchases(budget: Money, wanted: &[Wanted], offers: &[Offer]) -> Vec<Purchase> {
+    let mut left = wanted
+        .iter()
+        .map(|wanted| (wanted.article_id, wanted.count))
+        .collect::<HashMap<_, _>>();
+    let wanted = wanted
+        .iter()
+        .map(|wanted| (wanted.article_id, wanted))
+        .collect::<HashMap<_, _>>();
+
+    let mut candidates = offers
+        .iter()
+        .filter(|offer| offer.available > 0 && offer.unit_price.is_positive())
+        .filter_map(|offer| {
+            let wanted = wanted.get(&offer.article_id)?;
+            let margin = wanted.expected_price - offer.unit_price;
+            margin
+                .is_positive()
+                .then(|| (offer, *wanted, margin.as_f64() / offer.unit_price.as_f64()))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|(a, a_wanted, a_ratio), (b, b_wanted, b_ratio)| {
+        b_ratio
+            .partial_cmp(a_ratio)
+            .unwrap_or(Ordering::Equal)
+            .then(b_wanted.count.cmp(&a_wanted.count))
+            .then(a.article_id.cmp(&b.article_id))
+            .then(a.supplier_id.cmp(&b.supplier_id))
+    });
+
+    let mut money = budget;
+    let mut purchases = Vec::new();
+    for (offer, _, _) in candidates {
+        let left = left.get_mut(&offer.article_id).unwrap();
+        let affordable = (money.as_micros() / offer.unit_price.as_micros()).max(0);
+        let count = (*left)
+            .min(offer.available)
+            .min(usize::try_from(affordable).unwrap_or(usize::MAX));
+        if count == 0 {
+            continue;
+        }
+
+        let purchase = Purchase {
+            supplier_id: offer.supplier_id,
+            article_id: offer.article_id,
+            count,
+            unit_price: offer.unit_price,
+        };
+        money -= purchase.cost();
+        *left -= count;
+        purchases.push(purchase);
+    }
+
+    purchases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wanted(article_id: usize, count: usize, expected_price: f64) -> Wanted {
+        Wanted {
+            article_id: ArticleId(article_id),
+            count,
+            expected_price: Money::from_f64(expected_price),
+        }
+    }
+
+    fn offer(supplier_id: usize, article_id: usize, available: usize, unit_price: f64) -> Offer {
+        Offer {
+            supplier_id: SupplierId(supplier_id),
+            article_id: ArticleId(article_id),
+            available,
+            unit_price: Money::from_f64(unit_price),
+        }
+    }
+
+    fn purchase(supplier_id: usize, article_id: usize, count: usize, unit_price: f64) -> Purchase {
+        Purchase {
+            supplier_id: SupplierId(supplier_id),
+            article_id: ArticleId(article_id),
+            count,
+            unit_price: Money::from_f64(unit_price),
+        }
+    }
+
+    #[test]
+    fn buys_the_most_margin_per_money_first() {
+        let wanted = [wanted(1, 2, 20.0), wanted(2, 3, 30.0), wanted(3, 1, 10.0)];
+        let offers = [
+            offer(1, 1, 5, 10.0), // margin 10 for 10
+            offer(1, 2, 2, 10.0), // margin 20 for 10
+            offer(2, 2, 5, 20.0), // margin 10 for 20
+            offer(1, 3, 5, 12.0), // no margin
+            offer(1, 4, 5, 1.0),  // not wanted
+        ];
+
+        assert_eq!(
+            plan_purchases(Money::from_f64(1000.0), &wanted, &offers),
+            vec![
+                purchase(1, 2, 2, 10.0),
+                purchase(1, 1, 2, 10.0),
+                purchase(2, 2, 1, 20.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn spends_what_is_left_on_cheaper_units() {
+        let wanted = [wanted(1, 2, 40.0), wanted(2, 5, 6.0)];
+        let offers = [offer(1, 1, 5, 20.0), offer(1, 2, 5, 4.0)];
+
+        // The second unit of article 1 doesn't fit, but one of article 2 still does
+        assert_eq!(
+            plan_purchases(Money::from_f64(25.0), &wanted, &offers),
+            vec![purchase(1, 1, 1, 20.0), purchase(1, 2, 1, 4.0)]
+        );
+        assert_eq!(
+            plan_purchases(Money::from_f64(45.0), &wanted, &offers),
+            vec![purchase(1, 1, 2, 20.0), purchase(1, 2, 1, 4.0)]
+        );
+    }
+
+    #[test]
+    fn breaks_ties_by_count_and_ids() {
+        let wanted = [wanted(1, 1, 20.0), wanted(2, 3, 20.0), wanted(3, 1, 20.0)];
+        let offers = [
+            offer(1, 3, 5, 10.0),
+            offer(2, 2, 1, 10.0),
+            offer(1, 2, 1, 10.0),
+            offer(1, 1, 5, 10.0),
+        ];
+
+        assert_eq!(
+            plan_purchases(Money::from_f64(30.0), &wanted, &offers),
+            vec![
+                purchase(1, 2, 1, 10.0),
+                purchase(2, 2, 1, 10.0),
+                purchase(1, 1, 1, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefers_the_lower_supplier_id_whatever_the_order() {
+        let wanted = [wanted(1, 3, 20.0)];
+        let offers = [offer(2, 1, 5, 10.0), offer(1, 1, 2, 10.0)];
+
+        assert_eq!(
+            plan_purchases(Money::from_f64(1000.0), &wanted, &offers),
+            vec![purchase(1, 1, 2, 10.0), purchase(2, 1, 1, 10.0)]
+        );
+    }
+
+    #[test]
+    fn buys_nothing_without_money() {
+        let wanted = [wanted(1, 2, 20.0)];
+        let offers = [offer(1, 1, 5, 10.0)];
+
+        assert!(plan_purchases(Money::ZERO, &wanted, &offers).is_empty());
+        assert!(plan_purchases(Money::from_f64(9.99), &wanted, &offers).is_empty());
+    }
+}
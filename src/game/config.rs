@@ -0,0 +1,791 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::client::{
+    Money, RateLimit, RateLimiter, RetryPolicy, DEFAULT_ENDPOINT_LIMITS,
+    DEFAULT_REQUESTS_PER_SECOND,
+};
+use crate::logging::LogFormat;
+use crate::simulator::customers::CustomerModel;
+
+use super::{DemandObjective, MissedTickPolicy, StrategyKind, TrendSettings};
+
+// Environment variables starting with this prefix override single config keys,
+// e.g. MARKETPLACE_TAG_TREND_PRICE_REDUCTION=0.2 or MARKETPLACE_ENGINE_TICK_TIMER_IN_MS=10000
+pub static ENV_PREFIX: &str = "MARKETPLACE_";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub log: LogConfig,
+    pub metrics: MetricsConfig,
+    pub client: ClientConfig,
+    pub engine: EngineConfig,
+    pub tag_trend: TagTrendConfig,
+    pub demand: DemandConfig,
+    pub bandit: BanditConfig,
+    pub backtest: BacktestConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    // "human" or "json"
+    pub format: LogFormat,
+    // Which events to show, e.g. "info" or "warn,marketplacesimulation::client=debug".
+    // RUST_LOG takes precedence.
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Human,
+            filter: "info".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    // Serve Prometheus metrics on http://<listen>/metrics, e.g. "127.0.0.1:9100". Empty disables it
+    pub listen: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    // Every exchange with the server is appended here as one JSON line for replays, empty disables it
+    pub tape_file: String,
+    pub retry: RetryConfig,
+    pub rate_limit: RateLimitConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    // Including the first try, so 1 means no retries at all
+    pub max_attempts: u32,
+    // Delay before the first retry, doubled for every further one
+    pub base_delay_in_ms: u64,
+    pub max_delay_in_ms: u64,
+    // Share of the delay that is randomized (0.0 to 1.0), so we don't retry in lockstep
+    pub jitter: f64,
+    // Answers worth asking again. Buying and creating listings are never repeated after an answer.
+    pub retryable_statuses: Vec<u16>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        let policy = RetryPolicy::default();
+        Self {
+            max_attempts: policy.max_attempts,
+            base_delay_in_ms: policy.base_delay.as_millis() as u64,
+            max_delay_in_ms: policy.max_delay.as_millis() as u64,
+            jitter: policy.jitter,
+            retryable_statuses: policy
+                .retryable_statuses
+                .iter()
+                .map(|status| status.as_u16())
+                .collect(),
+        }
+    }
+}
+
+impl RetryConfig {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts,
+            base_delay: Duration::from_millis(self.base_delay_in_ms),
+            max_delay: Duration::from_millis(self.max_delay_in_ms),
+            jitter: self.jitter,
+            // Checked when the config was loaded
+            retryable_statuses: self
+                .retryable_statuses
+                .iter()
+                .filter_map(|status| StatusCode::from_u16(*status).ok())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    // Requests per second to all endpoints together, 0 disables the limit
+    pub requests_per_second: f64,
+    // Requests per second to single endpoints on top, e.g. "/listing" or "/listing/{id}" for
+    // all listing updates. 0 disables the limit.
+    pub endpoints: BTreeMap<String, f64>,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: DEFAULT_REQUESTS_PER_SECOND,
+            endpoints: DEFAULT_ENDPOINT_LIMITS
+                .iter()
+                .map(|(endpoint, requests_per_second)| (endpoint.to_string(), *requests_per_second))
+                .collect(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    pub fn rate_limiter(&self) -> RateLimiter {
+        let global = Some(self.requests_per_second)
+            .filter(|requests_per_second| *requests_per_second > 0.0)
+            .map(RateLimit::per_second);
+        self.endpoints
+            .iter()
+            .filter(|(_, requests_per_second)| **requests_per_second > 0.0)
+            .fold(
+                RateLimiter::new(global),
+                |limiter, (endpoint, requests_per_second)| {
+                    limiter
+                        .with_endpoint_limit(endpoint, RateLimit::per_second(*requests_per_second))
+                },
+            )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    // "tag-trend" or "bandit"
+    pub strategy: StrategyKind,
+    // Length of one simulation tick
+    pub tick_timer_in_ms: u64,
+    // Pause after a tick failed to fetch the market
+    pub stand_down_timer_in_ms: u64,
+    // How often we look at other players' listings between ticks
+    pub check_others_timer_in_ms: u64,
+    // "skip" ticks we missed because a tick ran long, or play them back to back with "burst"
+    pub missed_ticks: MissedTickPolicy,
+    // On shutdown, lower our listings to the average supplier price times the multiplier
+    // and list leftover stock at it, so everything sells while we are gone
+    pub wind_down_reprice: bool,
+    pub wind_down_price_multiplier: f64,
+    // Where the bot state is saved after every tick and resumed from on start, empty disables it
+    pub state_file: String,
+    // Every tick's market is appended here as one JSON line for backtesting, empty disables it
+    pub snapshot_file: String,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            strategy: StrategyKind::TagTrend,
+            tick_timer_in_ms: 30000,
+            stand_down_timer_in_ms: 5000, // The client already retried, give the server some rest
+            check_others_timer_in_ms: 100,
+            missed_ticks: MissedTickPolicy::Skip,
+            wind_down_reprice: false,
+            wind_down_price_multiplier: 1.0,
+            state_file: "marketplacesimulation-state.json".to_string(),
+            snapshot_file: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagTrendConfig {
+    pub sell_below_average_after_seconds: u64,
+    pub dont_buy_based_on_tag_level_after_seconds: u64,
+    pub dont_buy_one_of_everything_after_seconds: u64,
+
+    pub bedazzle_after_seconds: u64,
+
+    // Reduce price of unselled items in steps of this share
+    pub price_reduction: f64,
+    // Increase price of sold items in steps of this share
+    pub price_increase: f64,
+
+    // Trend score a unit sold at the average supplier price adds to the tags of its article,
+    // units sold at a higher price add more
+    pub tag_level_increase: f64,
+    // Scores halve every this many seconds
+    pub tag_trend_half_life_seconds: u64,
+    // How many steps through similar tags a sale reaches, and the share passed on with each
+    pub tag_trend_hops: usize,
+    pub tag_trend_damping: f64,
+    // Buy one unit per point of score of every article of a tag with at least this score
+    pub tag_level_buy_threshold: f64,
+
+    // Start selling at a much higher price
+    pub high_average_price_selling_multiplier: f64,
+    // Sell this much above average price at most
+    pub low_average_price_selling_multiplier: f64,
+    // Buy this much above average price at most
+    pub average_price_buying_multiplier: f64,
+
+    // Divide earnings by this amount to get the amount to put in the piggybank
+    pub piggybank_divider: f64,
+
+    // Reprice to the best price by the demand curve instead of stepping, once it knows one
+    pub demand_pricing: bool,
+}
+
+impl Default for TagTrendConfig {
+    fn default() -> Self {
+        Self {
+            sell_below_average_after_seconds: 23 * 60 * 60, // 23h
+            dont_buy_based_on_tag_level_after_seconds: 23 * 60 * 60, // 23h
+            dont_buy_one_of_everything_after_seconds: 23 * 60 * 60, // 23h
+
+            bedazzle_after_seconds: 10 * 60, // 10m
+
+            price_reduction: 0.1, // 10% steps
+            price_increase: 0.05, // 5% steps
+
+            tag_level_increase: 0.5,
+            tag_trend_half_life_seconds: 5 * 60, // 5m
+            tag_trend_hops: 1,
+            tag_trend_damping: 0.25,
+            tag_level_buy_threshold: 1.0,
+
+            // In my tests the simulated customers had no problem with a 10x price increase, anything higher will scare them off though.
+            // However, if other players other the same article for a lower price they will prefer that one.
+            high_average_price_selling_multiplier: 10.0,
+            low_average_price_selling_multiplier: 1.1, // 10% above average
+            average_price_buying_multiplier: 1.1,      // 10% above average
+
+            piggybank_divider: 3.0,
+
+            demand_pricing: false,
+        }
+    }
+}
+
+impl TagTrendConfig {
+    pub fn trend_settings(&self) -> TrendSettings {
+        TrendSettings {
+            sale_weight: self.tag_level_increase,
+            half_life: Duration::from_secs(self.tag_trend_half_life_seconds),
+            hops: self.tag_trend_hops,
+            damping: self.tag_trend_damping,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DemandConfig {
+    // Ticks of history kept per article
+    pub window: usize,
+    // Articles with fewer ticks on sale use the curve of one of their tags
+    pub min_observations: usize,
+    // "revenue" or "profit"
+    pub objective: DemandObjective,
+    // Look for the best price this share beyond the prices we have seen
+    pub explore: f64,
+}
+
+impl Default for DemandConfig {
+    fn default() -> Self {
+        Self {
+            window: 200,
+            min_observations: 10,
+            objective: DemandObjective::Profit,
+            explore: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BanditConfig {
+    // The prices to choose from, as multiples of the average supplier price
+    pub multipliers: Vec<f64>,
+    // Weight of the bonus for prices we know little about, in profits of one average supplier
+    // price per tick
+    pub exploration: f64,
+    // The bonus halves every this many seconds of the game
+    pub exploration_half_life_seconds: u64,
+
+    // Keep this many units of every article, listed or in stock
+    pub stock_per_article: usize,
+    // Buy this much above average price at most
+    pub average_price_buying_multiplier: f64,
+    // Never spend this last bit of money
+    pub reserve: Money,
+}
+
+impl Default for BanditConfig {
+    fn default() -> Self {
+        Self {
+            multipliers: vec![1.1, 1.25, 1.5, 2.0, 3.0, 5.0, 8.0],
+            exploration: 5.0,
+            exploration_half_life_seconds: 2 * 60 * 60, // 2h
+
+            stock_per_article: 3,
+            average_price_buying_multiplier: 1.1, // 10% above average
+            reserve: Money::from_f64(500.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BacktestConfig {
+    // Same seed, same customers. 0 picks a random one
+    pub seed: u64,
+    // 0 starts with the money of the recorded player
+    pub starting_money: Money,
+
+    pub customers_per_tick: usize,
+    pub max_units_per_customer: usize,
+    // Nobody buys at or above this multiple of the supplier price
+    pub max_price_multiplier: f64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        let customers = CustomerModel::default();
+        Self {
+            seed: 0,
+            starting_money: Money::ZERO,
+
+            customers_per_tick: customers.customers_per_tick,
+            max_units_per_customer: customers.max_units_per_customer,
+            max_price_multiplier: customers.max_price_multiplier,
+        }
+    }
+}
+
+impl BacktestConfig {
+    pub fn customer_model(&self) -> CustomerModel {
+        CustomerModel {
+            customers_per_tick: self.customers_per_tick,
+            max_units_per_customer: self.max_units_per_customer,
+            max_price_multiplier: self.max_price_multiplier,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(std::io::Error),
+    Parse(String),
+    Env { key: String, reason: String },
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(e) => write!(f, "Failed to read config file ({})", e),
+            ConfigError::Parse(e) => write!(f, "Failed to parse config file ({})", e),
+            ConfigError::Env { key, reason } => {
+                write!(f, "Invalid environment override {} ({})", key, reason)
+            }
+            ConfigError::Invalid(problems) => {
+                write!(f, "Invalid config: {}", problems.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // Read the config file (TOML, or JSON if the file ends with .json), apply environment
+    // overrides and validate the result. Without a file we start from the defaults.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let config = match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path).map_err(ConfigError::Read)?;
+                if path.extension().is_some_and(|ext| ext == "json") {
+                    serde_json::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?
+                } else {
+                    toml::from_str(&content).map_err(|e| ConfigError::Parse(e.to_string()))?
+                }
+            }
+            None => Config::default(),
+        };
+
+        config.with_overrides(std::env::vars())
+    }
+
+    // Apply the environment overrides among the variables and validate the result
+    fn with_overrides(
+        self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let mut value =
+            toml::Value::try_from(self).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        apply_env_overrides(&mut value, vars)?;
+
+        let config: Config = value
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.filter) {
+            problems.push(format!("log.filter is invalid ({})", e));
+        }
+
+        if !self.metrics.listen.is_empty()
+            && self.metrics.listen.parse::<std::net::SocketAddr>().is_err()
+        {
+            problems.push("metrics.listen must be an address like 127.0.0.1:9100".to_string());
+        }
+
+        let retry = &self.client.retry;
+        if retry.max_attempts == 0 {
+            problems.push("client.retry.max_attempts must be above 0".to_string());
+        }
+        if retry.base_delay_in_ms > retry.max_delay_in_ms {
+            problems.push(
+                "client.retry.base_delay_in_ms must not exceed client.retry.max_delay_in_ms"
+                    .to_string(),
+            );
+        }
+        if !(0.0..=1.0).contains(&retry.jitter) {
+            problems.push("client.retry.jitter must be in [0, 1]".to_string());
+        }
+        for status in &retry.retryable_statuses {
+            if !(400..=599).contains(status) {
+                problems.push(format!(
+                    "client.retry.retryable_statuses must be error statuses (400 to 599), got {}",
+                    status
+                ));
+            }
+        }
+
+        let rate_limit = &self.client.rate_limit;
+        if !(0.0..).contains(&rate_limit.requests_per_second) {
+            problems.push("client.rate_limit.requests_per_second must not be negative".to_string());
+        }
+        for (endpoint, requests_per_second) in &rate_limit.endpoints {
+            if !endpoint.starts_with('/') {
+                problems.push(format!(
+                    "client.rate_limit.endpoints must start with /, got {}",
+                    endpoint
+                ));
+            }
+            if !(0.0..).contains(requests_per_second) {
+                problems.push(format!(
+                    "client.rate_limit.endpoints.\"{}\" must not be negative",
+                    endpoint
+                ));
+            }
+        }
+
+        let engine = &self.engine;
+        if engine.tick_timer_in_ms == 0 {
+            problems.push("engine.tick_timer_in_ms must be above 0".to_string());
+        }
+        if engine.check_others_timer_in_ms == 0
+            || engine.check_others_timer_in_ms >= engine.tick_timer_in_ms
+        {
+            problems.push(
+                "engine.check_others_timer_in_ms must be above 0 and below engine.tick_timer_in_ms"
+                    .to_string(),
+            );
+        }
+
+        if engine.wind_down_price_multiplier.is_nan() || engine.wind_down_price_multiplier <= 0.0 {
+            problems.push("engine.wind_down_price_multiplier must be above 0".to_string());
+        }
+
+        let tag_trend = &self.tag_trend;
+        if !(0.0..1.0).contains(&tag_trend.price_reduction) {
+            problems.push("tag_trend.price_reduction must be in [0, 1)".to_string());
+        }
+        if !(0.0..).contains(&tag_trend.price_increase) {
+            problems.push("tag_trend.price_increase must not be negative".to_string());
+        }
+        for (key, multiplier) in [
+            (
+                "tag_trend.high_average_price_selling_multiplier",
+                tag_trend.high_average_price_selling_multiplier,
+            ),
+            (
+                "tag_trend.low_average_price_selling_multiplier",
+                tag_trend.low_average_price_selling_multiplier,
+            ),
+            (
+                "tag_trend.average_price_buying_multiplier",
+                tag_trend.average_price_buying_multiplier,
+            ),
+        ] {
+            if multiplier.is_nan() || multiplier <= 0.0 {
+                problems.push(format!("{} must be above 0", key));
+            }
+        }
+        if tag_trend.low_average_price_selling_multiplier
+            > tag_trend.high_average_price_selling_multiplier
+        {
+            problems.push(
+                "tag_trend.low_average_price_selling_multiplier must not exceed tag_trend.high_average_price_selling_multiplier"
+                    .to_string(),
+            );
+        }
+        if !(0.0..).contains(&tag_trend.tag_level_increase) {
+            problems.push("tag_trend.tag_level_increase must not be negative".to_string());
+        }
+        if tag_trend.tag_trend_half_life_seconds == 0 {
+            problems.push("tag_trend.tag_trend_half_life_seconds must be above 0".to_string());
+        }
+        if !(0.0..=1.0).contains(&tag_trend.tag_trend_damping) {
+            problems.push("tag_trend.tag_trend_damping must be in [0, 1]".to_string());
+        }
+        if tag_trend.tag_level_buy_threshold.is_nan() || tag_trend.tag_level_buy_threshold <= 0.0 {
+            problems.push("tag_trend.tag_level_buy_threshold must be above 0".to_string());
+        }
+        if !(1.0..).contains(&tag_trend.piggybank_divider) {
+            problems.push("tag_trend.piggybank_divider must be at least 1".to_string());
+        }
+
+        let demand = &self.demand;
+        if demand.min_observations < 2 {
+            problems.push("demand.min_observations must be at least 2".to_string());
+        }
+        if demand.window < demand.min_observations {
+            problems.push("demand.window must not be below demand.min_observations".to_string());
+        }
+        if !(0.0..).contains(&demand.explore) {
+            problems.push("demand.explore must not be negative".to_string());
+        }
+
+        let bandit = &self.bandit;
+        if bandit.multipliers.is_empty()
+            || bandit
+                .multipliers
+                .iter()
+                .any(|multiplier| multiplier.is_nan() || *multiplier <= 0.0)
+        {
+            problems.push("bandit.multipliers must not be empty and all above 0".to_string());
+        }
+        if !(0.0..).contains(&bandit.exploration) {
+            problems.push("bandit.exploration must not be negative".to_string());
+        }
+        if bandit.exploration_half_life_seconds == 0 {
+            problems.push("bandit.exploration_half_life_seconds must be above 0".to_string());
+        }
+        if bandit.average_price_buying_multiplier.is_nan()
+            || bandit.average_price_buying_multiplier <= 0.0
+        {
+            problems.push("bandit.average_price_buying_multiplier must be above 0".to_string());
+        }
+        if bandit.reserve.is_negative() {
+            problems.push("bandit.reserve must not be negative".to_string());
+        }
+
+        let backtest = &self.backtest;
+        if backtest.starting_money.is_negative() {
+            problems.push("backtest.starting_money must not be negative".to_string());
+        }
+        if backtest.max_units_per_customer == 0 {
+            problems.push("backtest.max_units_per_customer must be above 0".to_string());
+        }
+        if backtest.max_price_multiplier.is_nan() || backtest.max_price_multiplier <= 1.0 {
+            problems.push("backtest.max_price_multiplier must be above 1".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match toml::to_string_pretty(self) {
+            Ok(config) => write!(f, "{}", config.trim_end()),
+            Err(_) => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// MARKETPLACE_<SECTION>_<KEY>, e.g. MARKETPLACE_CLIENT_RETRY_MAX_ATTEMPTS for keys of nested
+// sections. The new value has to have the same type as the old one.
+fn apply_env_overrides(
+    value: &mut toml::Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Result<(), ConfigError> {
+    let sections = match value.as_table_mut() {
+        Some(sections) => sections,
+        None => return Ok(()),
+    };
+
+    for (key, raw) in vars {
+        let name = match key.strip_prefix(ENV_PREFIX) {
+            Some(name) => name.to_lowercase(),
+            None => continue,
+        };
+
+        let target = match find_key(sections, &name) {
+            Some(target) => target,
+            None => continue, // Not one of ours (e.g. MARKETPLACE_CONFIG)
+        };
+
+        let invalid = |reason: &str| ConfigError::Env {
+            key: key.clone(),
+            reason: reason.to_string(),
+        };
+        *target = match target {
+            toml::Value::Integer(_) => {
+                toml::Value::Integer(raw.parse().map_err(|_| invalid("expected an integer"))?)
+            }
+            toml::Value::Float(_) => {
+                toml::Value::Float(raw.parse().map_err(|_| invalid("expected a number"))?)
+            }
+            toml::Value::Boolean(_) => {
+                toml::Value::Boolean(raw.parse().map_err(|_| invalid("expected true or false"))?)
+            }
+            toml::Value::String(_) => toml::Value::String(raw.clone()),
+            _ => return Err(invalid("can't be set from the environment")),
+        };
+    }
+
+    Ok(())
+}
+
+// The key the underscored name leads to, through as many sections as it takes
+fn find_key<'a>(table: &'a mut toml::Table, name: &str) -> Option<&'a mut toml::Value> {
+    table.iter_mut().find_map(|(key, value)| {
+        if key == name && !value.is_table() {
+            return Some(value);
+        }
+        let rest = name.strip_prefix(key.as_str())?.strip_prefix('_')?;
+        find_key(value.as_table_mut()?, rest)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(toml: &str, vars: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let config: Config = toml::from_str(toml).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.with_overrides(
+            vars.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string())),
+        )
+    }
+
+    fn problems(result: Result<Config, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid config, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn environment_wins_over_the_file() {
+        let config = load(
+            "[engine]\ntick_timer_in_ms = 20000\n\n[client.retry]\nmax_attempts = 6\n",
+            &[
+                ("MARKETPLACE_ENGINE_TICK_TIMER_IN_MS", "10000"),
+                ("MARKETPLACE_CLIENT_RETRY_MAX_ATTEMPTS", "2"),
+                ("MARKETPLACE_TAG_TREND_PRICE_REDUCTION", "0.2"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.engine.tick_timer_in_ms, 10000);
+        assert_eq!(config.client.retry.max_attempts, 2);
+        assert_eq!(config.tag_trend.price_reduction, 0.2);
+    }
+
+    #[test]
+    fn keeps_the_file_without_overrides() {
+        let config = load(
+            "[engine]\ntick_timer_in_ms = 20000\n",
+            &[
+                ("MARKETPLACE_CONFIG", "marketplacesimulation.toml"),
+                ("PATH", "/usr/bin"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.engine.tick_timer_in_ms, 20000);
+        assert_eq!(config.engine.check_others_timer_in_ms, 100);
+    }
+
+    #[test]
+    fn rejects_overrides_of_the_wrong_type() {
+        match load("", &[("MARKETPLACE_ENGINE_TICK_TIMER_IN_MS", "soon")]) {
+            Err(ConfigError::Env { key, reason }) => {
+                assert_eq!(key, "MARKETPLACE_ENGINE_TICK_TIMER_IN_MS");
+                assert_eq!(reason, "expected an integer");
+            }
+            other => panic!("expected an invalid override, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_a_zero_tick() {
+        let problems = problems(load("", &[("MARKETPLACE_ENGINE_TICK_TIMER_IN_MS", "0")]));
+
+        assert!(problems.contains(&"engine.tick_timer_in_ms must be above 0".to_string()));
+    }
+
+    #[test]
+    fn rejects_jitter_above_one() {
+        let problems = problems(load("[client.retry]\njitter = 1.5\n", &[]));
+
+        assert_eq!(problems, vec!["client.retry.jitter must be in [0, 1]"]);
+    }
+
+    #[test]
+    fn rejects_unknown_strategies() {
+        for result in [
+            load("[engine]\nstrategy = \"martingale\"\n", &[]),
+            load("", &[("MARKETPLACE_ENGINE_STRATEGY", "martingale")]),
+        ] {
+            match result {
+                Err(ConfigError::Parse(e)) => {
+                    assert!(e.contains("unknown variant `martingale`"), "{}", e)
+                }
+                other => panic!("expected a parse error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(matches!(
+            load("[engine]\ntick_timer = 1000\n", &[]),
+            Err(ConfigError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let problems = problems(load(
+            "[tag_trend]\nprice_reduction = 1.0\n\n[demand]\nexplore = -1.0\n",
+            &[],
+        ));
+
+        assert_eq!(
+            problems,
+            vec![
+                "tag_trend.price_reduction must be in [0, 1)",
+                "demand.explore must not be negative",
+            ]
+        );
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::types::{ArticleId, Listing, Money};
+use crate::client::MarketSnapshot;
+
+use super::config::DemandConfig;
+use super::reconcile::Sale;
+
+// Prices we try when looking for the best one, spread over the prices we have seen
+const PRICE_STEPS: usize = 50;
+
+// What to get the most of when picking a price
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DemandObjective {
+    Revenue,
+    // Revenue minus what the units cost to replace at the average supplier price
+    #[default]
+    Profit,
+}
+
+// One tick of an article on sale: what we asked, what the cheapest other player asked and
+// how many units customers bought from us
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub price: Money,
+    pub competitor_price: Option<Money>,
+    // The average supplier price at the time. Prices are compared relative to it, so
+    // articles of different price ranges can share a curve.
+    pub average_price: Money,
+    pub units_sold: usize,
+}
+
+impl Observation {
+    fn relative_price(&self) -> f64 {
+        self.price.as_f64() / self.average_price.as_f64()
+    }
+
+    fn undercut(&self) -> bool {
+        self.competitor_price
+            .is_some_and(|competitor_price| competitor_price < self.price)
+    }
+}
+
+// Units sold per tick as a straight line over the price relative to the average supplier price,
+// shifted down when another player offers the article for less
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DemandCurve {
+    // Change in units per tick when the relative price goes up by 1, always negative
+    pub slope: f64,
+    pub level: f64,
+    pub undercut_level: f64,
+    // The relative prices we have seen, we don't trust the line beyond them
+    pub min_relative_price: f64,
+    pub max_relative_price: f64,
+    pub observations: usize,
+}
+
+impl DemandCurve {
+    // Least squares fit with a separate level for ticks we were undercut and ticks we weren't.
+    // Without a falling slope there is nothing to learn about prices from the observations.
+    pub fn fit<'a>(observations: impl Iterator<Item = &'a Observation>) -> Option<Self> {
+        let points = observations
+            .filter(|observation| observation.average_price.is_positive())
+            .map(|observation| {
+                (
+                    observation.relative_price(),
+                    observation.units_sold as f64,
+                    observation.undercut(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Means per group, the slope is fitted within the groups
+        let mut sums = [(0.0, 0.0, 0usize); 2];
+        for (x, y, undercut) in &points {
+            let sum = &mut sums[*undercut as usize];
+            sum.0 += x;
+            sum.1 += y;
+            sum.2 += 1;
+        }
+        let means = sums.map(|(x, y, n)| (n > 0).then(|| (x / n as f64, y / n as f64)));
+
+        let (mut covariance, mut variance) = (0.0, 0.0);
+        for (x, y, undercut) in &points {
+            let (mean_x, mean_y) = means[*undercut as usize].unwrap();
+            covariance += (x - mean_x) * (y - mean_y);
+            variance += (x - mean_x) * (x - mean_x);
+        }
+        if variance < f64::EPSILON {
+            return None;
+        }
+        let slope = covariance / variance;
+        if slope >= 0.0 {
+            return None;
+        }
+
+        let level = |(mean_x, mean_y): (f64, f64)| mean_y - slope * mean_x;
+        let (level, undercut_level) = match means {
+            [Some(not_undercut), Some(undercut)] => (level(not_undercut), level(undercut)),
+            [Some(only), None] | [None, Some(only)] => (level(only), level(only)),
+            [None, None] => return None,
+        };
+
+        let relative_prices = points.iter().map(|(x, _, _)| *x);
+        Some(Self {
+            slope,
+            level,
+            undercut_level,
+            min_relative_price: relative_prices.clone().fold(f64::INFINITY, f64::min),
+            max_relative_price: relative_prices.fold(f64::NEG_INFINITY, f64::max),
+            observations: points.len(),
+        })
+    }
+
+    pub fn units(&self, relative_price: f64, undercut: bool) -> f64 {
+        let level = if undercut {
+            self.undercut_level
+        } else {
+            self.level
+        };
+        (level + self.slope * relative_price).max(0.0)
+    }
+
+    // Relative change in units per relative change in price, None where nothing sells
+    pub fn elasticity(&self, relative_price: f64, undercut: bool) -> Option<f64> {
+        let units = self.units(relative_price, undercut);
+        (units > 0.0).then(|| self.slope * relative_price / units)
+    }
+}
+
+// The prices we offered per article and what sold at them. Every tick is one observation per
+// article, articles with too few of them borrow the curve of one of their tags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DemandModel {
+    #[serde(skip)]
+    config: DemandConfig,
+    observations: HashMap<ArticleId, VecDeque<Observation>>,
+    // What is on sale this tick, without the units sold yet
+    open: HashMap<ArticleId, Observation>,
+}
+
+impl DemandModel {
+    pub fn new(config: DemandConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    // Take the observations of a previous run, keep our config
+    pub fn restore(&mut self, saved: DemandModel) {
+        self.observations = saved.observations;
+        self.open = saved.open;
+    }
+
+    // Start a tick with our listings at the prices they have for it. The cheapest listing of
+    // an article is the one customers see.
+    pub fn open(&mut self, snapshot: &MarketSnapshot, own_listings: &[Listing]) {
+        self.open.clear();
+        for listing in own_listings.iter().filter(|listing| listing.count > 0) {
+            let average_price = match snapshot.article_price_history.get(&listing.article) {
+                Some(history) => history.average_price(),
+                None => continue,
+            };
+            let observation = Observation {
+                price: listing.price,
+                competitor_price: competitor_price(snapshot, listing.article),
+                average_price,
+                units_sold: 0,
+            };
+            self.open
+                .entry(listing.article)
+                .and_modify(|open| {
+                    if observation.price < open.price {
+                        *open = observation;
+                    }
+                })
+                .or_insert(observation);
+        }
+    }
+
+    // Sales of articles we didn't have on sale at the start of the tick are dropped
+    pub fn record_sales(&mut self, sales: &[Sale]) {
+        for sale in sales {
+            if let Some(open) = self.open.get_mut(&sale.article_id) {
+                open.units_sold += sale.count;
+            }
+        }
+    }
+
+    // End the tick, what was on sale becomes an observation
+    pub fn close(&mut self) {
+        for (article_id, observation) in self.open.drain() {
+            let observations = self.observations.entry(article_id).or_default();
+            observations.push_back(observation);
+            while observations.len() > self.config.window {
+                observations.pop_front();
+            }
+        }
+    }
+
+    // End the tick and start the next one at the same prices, for ticks we don't reprice in
+    pub fn carry_over(&mut self) {
+        let open = self
+            .open
+            .iter()
+            .map(|(article_id, observation)| {
+                let observation = Observation {
+                    units_sold: 0,
+                    ..*observation
+                };
+                (*article_id, observation)
+            })
+            .collect();
+        self.close();
+        self.open = open;
+    }
+
+    pub fn observations(&self, article_id: ArticleId) -> impl Iterator<Item = &Observation> {
+        self.observations.get(&article_id).into_iter().flatten()
+    }
+
+    // The article's own curve if it has enough observations, otherwise the curve of the tag
+    // of the article with the most observations
+    pub fn curve(&self, snapshot: &MarketSnapshot, article_id: ArticleId) -> Option<DemandCurve> {
+        if self.observations(article_id).count() >= self.config.min_observations {
+            if let Some(curve) = DemandCurve::fit(self.observations(article_id)) {
+                return Some(curve);
+            }
+        }
+
+        let tags = &snapshot.article(article_id)?.tags;
+        tags.iter()
+            .filter_map(|tag_id| {
+                let observations = snapshot
+                    .articles_with_tag(*tag_id)
+                    .flat_map(|article| self.observations(article.id));
+                DemandCurve::fit(observations)
+            })
+            .filter(|curve| curve.observations >= self.config.min_observations)
+            .max_by_key(|curve| curve.observations)
+    }
+
+    // The price that brings the most by the curve, given the other players' listings now. None
+    // until we know enough about the article or its tags.
+    pub fn best_price(&self, snapshot: &MarketSnapshot, article_id: ArticleId) -> Option<Money> {
+        let curve = self.curve(snapshot, article_id)?;
+        let average_price = snapshot
+            .article_price_history
+            .get(&article_id)?
+            .average_price()
+            .as_f64();
+        let unit_cost = match self.config.objective {
+            DemandObjective::Revenue => 0.0,
+            DemandObjective::Profit => average_price,
+        };
+        let competitor_price = competitor_price(snapshot, article_id).map(Money::as_f64);
+
+        // A little beyond the prices we have seen, otherwise we never learn about others. The
+        // curve jumps where we stop undercutting, so just below the competitor is a candidate.
+        let explore = 1.0 + self.config.explore;
+        let (min, max) = (
+            curve.min_relative_price / explore,
+            curve.max_relative_price * explore,
+        );
+        let mut candidates = (0..=PRICE_STEPS)
+            .map(|step| min + (max - min) * step as f64 / PRICE_STEPS as f64)
+            .collect::<Vec<_>>();
+        if let Some(competitor_price) = competitor_price {
+            let below = (competitor_price - 0.000001) / average_price;
+            if (min..=max).contains(&below) {
+                candidates.push(below);
+            }
+        }
+
+        let value = |relative_price: f64| {
+            let price = relative_price * average_price;
+            let undercut = competitor_price.is_some_and(|competitor| competitor < price);
+            curve.units(relative_price, undercut) * (price - unit_cost)
+        };
+        candidates
+            .into_iter()
+            .map(|relative_price| (relative_price, value(relative_price)))
+            .filter(|(_, value)| *value > 0.0)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(relative_price, _)| Money::from_f64(relative_price * average_price))
+    }
+}
+
+// Cheapest listing of another player that still has units
+fn competitor_price(snapshot: &MarketSnapshot, article_id: ArticleId) -> Option<Money> {
+    snapshot
+        .listings_for_article(article_id)
+        .filter(|listing| listing.player != snapshot.player.id && listing.count > 0)
+        .map(|listing| listing.price)
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::types::ListingId;
+    use crate::game::testing::{TestMarket, OTHER_PLAYER, PLAYER};
+
+    fn observation(price: f64, competitor_price: Option<f64>, units_sold: usize) -> Observation {
+        Observation {
+            price: Money::from_f64(price),
+            competitor_price: competitor_price.map(Money::from_f64),
+            average_price: Money::from_f64(10.0),
+            units_sold,
+        }
+    }
+
+    // Units = 10 - 4 * relative price, 5 - 4 * relative price while undercut. The average
+    // price is 10, the competitor undercuts at 4.
+    fn observations() -> Vec<Observation> {
+        let mut observations = [(10.0, 6), (12.5, 5), (15.0, 4), (17.5, 3), (20.0, 2)]
+            .iter()
+            .map(|(price, units)| observation(*price, None, *units))
+            .collect::<Vec<_>>();
+        observations.extend(
+            [(5.0, 3), (7.5, 2), (10.0, 1), (12.5, 0)]
+                .iter()
+                .map(|(price, units)| observation(*price, Some(4.0), *units)),
+        );
+        observations
+    }
+
+    fn model(objective: DemandObjective) -> DemandModel {
+        let mut model = DemandModel::new(DemandConfig {
+            window: 20,
+            min_observations: 5,
+            objective,
+            explore: 0.0,
+        });
+        model
+            .observations
+            .insert(ArticleId(1), observations().into_iter().collect());
+        model
+    }
+
+    fn market() -> TestMarket {
+        TestMarket::default()
+            .article(1, &[1], 10.0)
+            .article(2, &[1], 20.0)
+            .article(3, &[2], 10.0)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn fits_the_slope_and_both_levels() {
+        let curve = DemandCurve::fit(observations().iter()).unwrap();
+
+        assert_close(curve.slope, -4.0);
+        assert_close(curve.level, 10.0);
+        assert_close(curve.undercut_level, 5.0);
+        assert_close(curve.min_relative_price, 0.5);
+        assert_close(curve.max_relative_price, 2.0);
+        assert_eq!(curve.observations, 9);
+
+        assert_close(curve.units(1.5, false), 4.0);
+        assert_close(curve.units(1.5, true), 0.0);
+        assert_close(curve.elasticity(1.5, false).unwrap(), -1.5);
+        assert_eq!(curve.elasticity(1.5, true), None);
+    }
+
+    #[test]
+    fn doesnt_fit_without_a_falling_slope() {
+        let rising = [observation(10.0, None, 1), observation(20.0, None, 3)];
+        let one_price = [observation(10.0, None, 1), observation(10.0, None, 3)];
+
+        assert_eq!(DemandCurve::fit(rising.iter()), None);
+        assert_eq!(DemandCurve::fit(one_price.iter()), None);
+        assert_eq!(DemandCurve::fit([].iter()), None);
+    }
+
+    #[test]
+    fn needs_enough_observations_for_a_curve() {
+        let snapshot = market().snapshot();
+        let mut model = model(DemandObjective::Profit);
+
+        assert!(model.curve(&snapshot, ArticleId(1)).is_some());
+
+        model.config.min_observations = 10;
+        assert_eq!(model.curve(&snapshot, ArticleId(1)), None);
+    }
+
+    #[test]
+    fn borrows_the_curve_of_a_tag() {
+        let snapshot = market().snapshot();
+        let model = model(DemandObjective::Profit);
+
+        // Article 2 shares tag 1 with article 1, article 3 has nobody to learn from
+        let curve = model.curve(&snapshot, ArticleId(2)).unwrap();
+        assert_close(curve.slope, -4.0);
+        assert_eq!(model.curve(&snapshot, ArticleId(3)), None);
+    }
+
+    #[test]
+    fn keeps_a_window_of_observations() {
+        let mut model = DemandModel::new(DemandConfig {
+            window: 3,
+            ..DemandConfig::default()
+        });
+
+        for (tick, price) in [11.0, 12.0, 13.0, 14.0, 15.0].iter().enumerate() {
+            let snapshot = market().listing(1, PLAYER, 1, 5, *price).snapshot();
+            model.open(&snapshot, &snapshot.get_own_listings(&[]));
+            model.record_sales(&[Sale {
+                listing_id: ListingId(1),
+                article_id: ArticleId(1),
+                count: tick,
+                unit_price: Money::from_f64(*price),
+            }]);
+            model.close();
+        }
+
+        assert_eq!(
+            model
+                .observations(ArticleId(1))
+                .map(|observation| (observation.price.as_f64(), observation.units_sold))
+                .collect::<Vec<_>>(),
+            vec![(13.0, 2), (14.0, 3), (15.0, 4)]
+        );
+    }
+
+    #[test]
+    fn carries_prices_over_to_the_next_tick() {
+        let mut model = DemandModel::new(DemandConfig::default());
+        let snapshot = market()
+            .listing(1, PLAYER, 1, 5, 12.0)
+            .listing(2, OTHER_PLAYER, 1, 5, 11.0)
+            .snapshot();
+        let sale = Sale {
+            listing_id: ListingId(1),
+            article_id: ArticleId(1),
+            count: 2,
+            unit_price: Money::from_f64(12.0),
+        };
+
+        model.open(&snapshot, &snapshot.get_own_listings(&[]));
+        model.record_sales(&[sale]);
+        model.carry_over();
+        model.record_sales(&[sale]);
+        model.close();
+
+        let expected = observation(12.0, Some(11.0), 2);
+        assert_eq!(
+            model.observations(ArticleId(1)).collect::<Vec<_>>(),
+            vec![&expected, &expected]
+        );
+    }
+
+    #[test]
+    fn finds_the_price_with_the_most_revenue() {
+        let snapshot = market().snapshot();
+        let price = model(DemandObjective::Revenue)
+            .best_price(&snapshot, ArticleId(1))
+            .unwrap();
+
+        // Revenue 10 * r * (10 - 4 * r) peaks at r = 1.25, the grid is 0.03 wide
+        assert!((price.as_f64() - 12.5).abs() <= 0.15, "{}", price);
+    }
+
+    #[test]
+    fn finds_the_price_with_the_most_profit() {
+        let snapshot = market().snapshot();
+        let price = model(DemandObjective::Profit)
+            .best_price(&snapshot, ArticleId(1))
+            .unwrap();
+
+        // Profit 10 * (r - 1) * (10 - 4 * r) peaks at r = 1.75
+        assert!((price.as_f64() - 17.5).abs() <= 0.15, "{}", price);
+    }
+
+    #[test]
+    fn stays_just_below_a_competitor() {
+        // Above 15 we'd be undercut and sell nothing
+        let snapshot = market().listing(1, OTHER_PLAYER, 1, 5, 15.0).snapshot();
+        let price = model(DemandObjective::Profit)
+            .best_price(&snapshot, ArticleId(1))
+            .unwrap();
+
+        assert_eq!(price, Money::from_f64(14.999999));
+    }
+
+    #[test]
+    fn has_no_price_without_a_curve() {
+        let snapshot = market().snapshot();
+
+        assert_eq!(
+            model(DemandObjective::Profit).best_price(&snapshot, ArticleId(3)),
+            None
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::client::types::{ArticleId, Money, SupplierId, TagId};
+use crate::client::MarketSnapshot;
+
+use super::reconcile::Sale;
+
+// Units bought together at the same price. Sales use up the oldest lots first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lot {
+    pub supplier_id: SupplierId,
+    pub count: usize,
+    pub unit_cost: Money,
+}
+
+// Totals of one article (or one tag)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Book {
+    pub units_bought: usize,
+    pub spent: Money,
+    pub units_sold: usize,
+    pub revenue: Money,
+    // What the sold units cost us, by FIFO
+    pub cost_of_sales: Money,
+    // Sold units we have no purchase for, e.g. bought before the ledger existed. They are
+    // booked without cost.
+    pub uncosted_units: usize,
+}
+
+impl Book {
+    pub fn realized(&self) -> Money {
+        self.revenue - self.cost_of_sales
+    }
+
+    // Share of the revenue we kept, None before the first sale
+    pub fn margin(&self) -> Option<f64> {
+        if self.revenue.is_positive() {
+            Some(self.realized().as_f64() / self.revenue.as_f64())
+        } else {
+            None
+        }
+    }
+
+    fn add(&mut self, other: &Book) {
+        self.units_bought += other.units_bought;
+        self.spent += other.spent;
+        self.units_sold += other.units_sold;
+        self.revenue += other.revenue;
+        self.cost_of_sales += other.cost_of_sales;
+        self.uncosted_units += other.uncosted_units;
+    }
+}
+
+// What one supplier filled for us
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SupplierBook {
+    pub orders: usize,
+    pub units: usize,
+    pub spent: Money,
+}
+
+impl SupplierBook {
+    pub fn average_unit_price(&self) -> Money {
+        self.spent / self.units
+    }
+}
+
+// Every purchase and every sale we noticed, with the FIFO cost basis of what we still hold
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Ledger {
+    lots: HashMap<ArticleId, VecDeque<Lot>>,
+    books: BTreeMap<ArticleId, Book>,
+    #[serde(default)]
+    suppliers: BTreeMap<SupplierId, SupplierBook>,
+}
+
+impl Ledger {
+    pub fn record_buy(
+        &mut self,
+        supplier_id: SupplierId,
+        article_id: ArticleId,
+        count: usize,
+        unit_cost: Money,
+    ) {
+        self.lots.entry(article_id).or_default().push_back(Lot {
+            supplier_id,
+            count,
+            unit_cost,
+        });
+
+        let book = self.books.entry(article_id).or_default();
+        book.units_bought += count;
+        book.spent += unit_cost * count;
+
+        let supplier = self.suppliers.entry(supplier_id).or_default();
+        supplier.orders += 1;
+        supplier.units += count;
+        supplier.spent += unit_cost * count;
+    }
+
+    pub fn record_sale(&mut self, sale: &Sale) {
+        let lots = self.lots.entry(sale.article_id).or_default();
+        let book = self.books.entry(sale.article_id).or_default();
+
+        let mut remaining = sale.count;
+        while remaining > 0 {
+            let lot = match lots.front_mut() {
+                Some(lot) => lot,
+                None => break,
+            };
+            let taken = remaining.min(lot.count);
+            book.cost_of_sales += lot.unit_cost * taken;
+            lot.count -= taken;
+            remaining -= taken;
+            if lot.count == 0 {
+                lots.pop_front();
+            }
+        }
+        if remaining > 0 {
+            warn!(
+                article_id = %sale.article_id,
+                units = remaining,
+                "Weird. Sold units we never bought, booking them without cost"
+            );
+            book.uncosted_units += remaining;
+        }
+
+        book.units_sold += sale.count;
+        book.revenue += sale.unit_price * sale.count;
+    }
+
+    pub fn lots(&self, article_id: ArticleId) -> impl Iterator<Item = &Lot> {
+        self.lots.get(&article_id).into_iter().flatten()
+    }
+
+    pub fn by_supplier(&self) -> &BTreeMap<SupplierId, SupplierBook> {
+        &self.suppliers
+    }
+
+    pub fn realized(&self) -> Money {
+        self.books.values().map(Book::realized).sum()
+    }
+
+    // What we still hold, valued at the average supplier price, minus what it cost us
+    pub fn unrealized(&self, snapshot: &MarketSnapshot, article_id: ArticleId) -> Money {
+        let mark = match snapshot.article_price_history.get(&article_id) {
+            Some(history) => history.average_price(),
+            None => return Money::ZERO,
+        };
+        self.lots(article_id)
+            .map(|lot| (mark - lot.unit_cost) * lot.count)
+            .sum()
+    }
+
+    pub fn total_unrealized(&self, snapshot: &MarketSnapshot) -> Money {
+        self.books
+            .keys()
+            .map(|article_id| self.unrealized(snapshot, *article_id))
+            .sum()
+    }
+
+    pub fn by_article(&self, snapshot: &MarketSnapshot) -> BTreeMap<ArticleId, Pnl> {
+        self.books
+            .iter()
+            .map(|(article_id, book)| {
+                let pnl = Pnl {
+                    book: *book,
+                    unrealized: self.unrealized(snapshot, *article_id),
+                };
+                (*article_id, pnl)
+            })
+            .collect()
+    }
+
+    // Articles count towards every tag they have
+    pub fn by_tag(&self, snapshot: &MarketSnapshot) -> BTreeMap<TagId, Pnl> {
+        let mut by_tag = BTreeMap::<TagId, Pnl>::new();
+        for (article_id, article_pnl) in self.by_article(snapshot) {
+            let tags = match snapshot.article(article_id) {
+                Some(article) => &article.tags,
+                None => continue,
+            };
+            for tag_id in tags {
+                let pnl = by_tag.entry(*tag_id).or_default();
+                pnl.book.add(&article_pnl.book);
+                pnl.unrealized += article_pnl.unrealized;
+            }
+        }
+        by_tag
+    }
+}
+
+// Profit and loss of an article or a tag
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pnl {
+    pub book: Book,
+    pub unrealized: Money,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::types::ListingId;
+    use crate::game::testing::TestMarket;
+
+    fn money(amount: f64) -> Money {
+        Money::from_f64(amount)
+    }
+
+    fn sale(article_id: usize, count: usize, unit_price: f64) -> Sale {
+        Sale {
+            listing_id: ListingId(1),
+            article_id: ArticleId(article_id),
+            count,
+            unit_price: money(unit_price),
+        }
+    }
+
+    // 3 units at 10, then 2 at 16
+    fn two_lots() -> Ledger {
+        let mut ledger = Ledger::default();
+        ledger.record_buy(SupplierId(1), ArticleId(1), 3, money(10.0));
+        ledger.record_buy(SupplierId(2), ArticleId(1), 2, money(16.0));
+        ledger
+    }
+
+    #[test]
+    fn books_purchases() {
+        let ledger = two_lots();
+
+        let book = ledger.books[&ArticleId(1)];
+        assert_eq!(book.units_bought, 5);
+        assert_eq!(book.spent, money(62.0));
+        assert_eq!(ledger.realized(), Money::ZERO);
+        assert_eq!(
+            ledger.by_supplier()[&SupplierId(2)].average_unit_price(),
+            money(16.0)
+        );
+    }
+
+    #[test]
+    fn costs_sales_by_the_oldest_lot() {
+        let mut ledger = two_lots();
+        ledger.record_sale(&sale(1, 2, 25.0));
+
+        let book = ledger.books[&ArticleId(1)];
+        assert_eq!(book.cost_of_sales, money(20.0));
+        assert_eq!(book.realized(), money(30.0));
+        assert_eq!(
+            ledger
+                .lots(ArticleId(1))
+                .map(|lot| (lot.count, lot.unit_cost))
+                .collect::<Vec<_>>(),
+            vec![(1, money(10.0)), (2, money(16.0))]
+        );
+    }
+
+    #[test]
+    fn takes_sales_across_lots() {
+        let mut ledger = two_lots();
+        ledger.record_sale(&sale(1, 2, 25.0));
+        ledger.record_sale(&sale(1, 2, 20.0));
+
+        // One unit at 10 and one at 16 for the second sale
+        let book = ledger.books[&ArticleId(1)];
+        assert_eq!(book.cost_of_sales, money(46.0));
+        assert_eq!(book.revenue, money(90.0));
+        assert_eq!(ledger.realized(), money(44.0));
+        assert_eq!(
+            ledger
+                .lots(ArticleId(1))
+                .map(|lot| (lot.count, lot.unit_cost))
+                .collect::<Vec<_>>(),
+            vec![(1, money(16.0))]
+        );
+    }
+
+    #[test]
+    fn books_units_we_never_bought_without_cost() {
+        let mut ledger = two_lots();
+        ledger.record_sale(&sale(1, 7, 20.0));
+
+        let book = ledger.books[&ArticleId(1)];
+        assert_eq!(book.cost_of_sales, money(62.0));
+        assert_eq!(book.uncosted_units, 2);
+        assert_eq!(book.realized(), money(78.0));
+        assert_eq!(ledger.lots(ArticleId(1)).count(), 0);
+    }
+
+    #[test]
+    fn values_what_we_hold_at_the_average_price() {
+        let snapshot = TestMarket::default().article(1, &[], 12.0).snapshot();
+        let mut ledger = two_lots();
+        ledger.record_sale(&sale(1, 2, 25.0));
+
+        // 1 unit at 10 and 2 at 16, all worth 12
+        assert_eq!(ledger.unrealized(&snapshot, ArticleId(1)), money(-6.0));
+        assert_eq!(ledger.total_unrealized(&snapshot), money(-6.0));
+    }
+}
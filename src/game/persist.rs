@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::types::{ArticleId, ListingId, PlayerId};
+use crate::client::{ArticlePriceHistory, MarketState};
+
+use super::{Ledger, Reconciler};
+
+// Bump this whenever SavedState (or a strategy's saved state) changes incompatibly.
+// Files with another version are discarded instead of half-loaded.
+pub static SCHEMA_VERSION: u32 = 1;
+
+// Everything we need to pick up where we left off after a restart
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedState {
+    pub schema_version: u32,
+    // Wall clock time of the save in seconds since the unix epoch
+    pub saved_at: u64,
+
+    pub tick: u64,
+    // Time since the game started at the moment of the save
+    pub elapsed: Duration,
+
+    // Used to detect a game reset on the server
+    pub player_id: PlayerId,
+    pub article_ids: Vec<ArticleId>,
+    pub own_listing_ids: Vec<ListingId>,
+
+    pub article_price_history: HashMap<ArticleId, ArticlePriceHistory>,
+    // Whatever the strategy wants to keep, see Strategy::save_state
+    pub strategy: Option<serde_json::Value>,
+    // Cost basis of what we hold, so profits stay right across restarts
+    #[serde(default)]
+    pub ledger: Ledger,
+    // Our listings as we left them, whatever sells while we are gone is found when we are back
+    #[serde(default)]
+    pub reconciler: Reconciler,
+}
+
+impl SavedState {
+    pub fn capture(
+        state: &MarketState,
+        tick: u64,
+        elapsed: Duration,
+        strategy: Option<serde_json::Value>,
+        ledger: Ledger,
+        reconciler: Reconciler,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            saved_at: unix_time(),
+
+            tick,
+            elapsed,
+
+            player_id: state.player.id,
+            article_ids: article_ids(state),
+            own_listing_ids: own_listing_ids(state),
+
+            article_price_history: state.article_price_history.clone(),
+            strategy,
+            ledger,
+            reconciler,
+        }
+    }
+
+    // The game kept running while we were gone, so the downtime counts as well
+    pub fn resumed_elapsed(&self) -> Duration {
+        self.elapsed + Duration::from_secs(unix_time().saturating_sub(self.saved_at))
+    }
+
+    // Why this state doesn't belong to the game on the server anymore, if it doesn't
+    pub fn stale_reason(&self, state: &MarketState) -> Option<String> {
+        if self.player_id != state.player.id {
+            return Some(format!(
+                "player changed from {} to {}",
+                self.player_id, state.player.id
+            ));
+        }
+        if self.article_ids != article_ids(state) {
+            return Some("the articles changed".to_string());
+        }
+        // We never delete our own listings, a reset does
+        let own_listing_ids = own_listing_ids(state);
+        if !self.own_listing_ids.is_empty()
+            && !self
+                .own_listing_ids
+                .iter()
+                .any(|listing_id| own_listing_ids.contains(listing_id))
+        {
+            return Some("our listings are gone".to_string());
+        }
+
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum PersistError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    Version { found: u32, expected: u32 },
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistError::Io(e) => write!(f, "State file not accessible ({})", e),
+            PersistError::Format(e) => write!(f, "State file is corrupt ({})", e),
+            PersistError::Version { found, expected } => write!(
+                f,
+                "State file has schema version {}, expected {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PersistError {}
+
+pub struct StateStore {
+    path: PathBuf,
+}
+
+impl StateStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // No file means there is nothing to resume
+    pub fn load(&self) -> Result<Option<SavedState>, PersistError> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(PersistError::Io(e)),
+        };
+
+        // Look at the version first, the rest of an old file might not parse at all
+        #[derive(Deserialize)]
+        struct Versioned {
+            schema_version: u32,
+        }
+        let versioned: Versioned = serde_json::from_str(&content).map_err(PersistError::Format)?;
+        if versioned.schema_version != SCHEMA_VERSION {
+            return Err(PersistError::Version {
+                found: versioned.schema_version,
+                expected: SCHEMA_VERSION,
+            });
+        }
+
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(PersistError::Format)
+    }
+
+    // Write to a temporary file next to the real one and rename it, so a crash mid-write
+    // never leaves us with a truncated state file
+    pub fn save(&self, saved: &SavedState) -> Result<(), PersistError> {
+        let content = serde_json::to_vec(saved).map_err(PersistError::Format)?;
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut file = fs::File::create(&tmp_path).map_err(PersistError::Io)?;
+        file.write_all(&content).map_err(PersistError::Io)?;
+        file.sync_all().map_err(PersistError::Io)?;
+        fs::rename(&tmp_path, &self.path).map_err(PersistError::Io)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+fn article_ids(state: &MarketState) -> Vec<ArticleId> {
+    let mut article_ids = state
+        .articles
+        .iter()
+        .map(|article| article.id)
+        .collect::<Vec<_>>();
+    article_ids.sort_unstable();
+    article_ids
+}
+
+fn own_listing_ids(state: &MarketState) -> Vec<ListingId> {
+    state
+        .index()
+        .listings_of_player(&state.listings, state.player.id)
+        .map(|listing| listing.id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::types::{Money, SupplierId};
+    use crate::game::testing::{TestMarket, OTHER_PLAYER, PLAYER};
+
+    fn market() -> TestMarket {
+        TestMarket::default()
+            .article(1, &[], 10.0)
+            .article(2, &[], 20.0)
+            .listing(1, PLAYER, 1, 2, 15.0)
+            .listing(2, OTHER_PLAYER, 2, 1, 25.0)
+    }
+
+    fn state(market: &TestMarket) -> MarketState {
+        let mut state = MarketState::default();
+        market.fetch_into(&mut state);
+        state
+    }
+
+    fn saved(market: &TestMarket) -> SavedState {
+        SavedState::capture(
+            &state(market),
+            3,
+            Duration::from_secs(90),
+            None,
+            Ledger::default(),
+            Reconciler::default(),
+        )
+    }
+
+    #[test]
+    fn belongs_to_the_same_game() {
+        let saved = saved(&market());
+
+        assert_eq!(saved.stale_reason(&state(&market())), None);
+        // New listings of ours or of others don't matter
+        let market = market().listing(3, PLAYER, 2, 1, 30.0);
+        assert_eq!(saved.stale_reason(&state(&market)), None);
+    }
+
+    #[test]
+    fn is_stale_for_another_player() {
+        let saved = saved(&market());
+        let mut market = market();
+        market.player.id = OTHER_PLAYER;
+
+        assert_eq!(
+            saved.stale_reason(&state(&market)),
+            Some(format!(
+                "player changed from {} to {}",
+                PLAYER, OTHER_PLAYER
+            ))
+        );
+    }
+
+    #[test]
+    fn is_stale_for_other_articles() {
+        let saved = saved(&market());
+        let market = market().article(3, &[], 30.0);
+
+        assert_eq!(
+            saved.stale_reason(&state(&market)),
+            Some("the articles changed".to_string())
+        );
+    }
+
+    #[test]
+    fn is_stale_once_our_listings_are_gone() {
+        let saved = saved(&market());
+        let mut market = market();
+        market.listings.retain(|listing| listing.player != PLAYER);
+
+        assert_eq!(
+            saved.stale_reason(&state(&market)),
+            Some("our listings are gone".to_string())
+        );
+
+        // Without listings to miss there is nothing to tell
+        let saved = SavedState {
+            own_listing_ids: Vec::new(),
+            ..saved
+        };
+        assert_eq!(saved.stale_reason(&state(&market)), None);
+    }
+
+    #[test]
+    fn loads_nothing_without_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path().join("state.json"));
+
+        assert!(store.load().unwrap().is_none());
+    }
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path().join("state.json"));
+        let mut ledger = Ledger::default();
+        ledger.record_buy(SupplierId(1), ArticleId(1), 2, Money::from_f64(10.0));
+        let saved = SavedState::capture(
+            &state(&market()),
+            3,
+            Duration::from_secs(90),
+            Some(serde_json::json!({ "piggybank": 5 })),
+            ledger,
+            Reconciler::default(),
+        );
+
+        store.save(&saved).unwrap();
+        // Nothing temporary is left behind
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let loaded = store.load().unwrap().unwrap();
+        assert_eq!(loaded.schema_version, SCHEMA_VERSION);
+        assert_eq!(loaded.saved_at, saved.saved_at);
+        assert_eq!(loaded.tick, 3);
+        assert_eq!(loaded.elapsed, Duration::from_secs(90));
+        assert_eq!(loaded.player_id, PLAYER);
+        assert_eq!(loaded.article_ids, vec![ArticleId(1), ArticleId(2)]);
+        assert_eq!(loaded.own_listing_ids, vec![ListingId(1)]);
+        assert_eq!(
+            loaded.article_price_history[&ArticleId(2)].average_price(),
+            Money::from_f64(20.0)
+        );
+        assert_eq!(loaded.strategy, saved.strategy);
+        assert_eq!(
+            loaded
+                .ledger
+                .lots(ArticleId(1))
+                .map(|lot| (lot.count, lot.unit_cost))
+                .collect::<Vec<_>>(),
+            vec![(2, Money::from_f64(10.0))]
+        );
+        assert_eq!(loaded.stale_reason(&state(&market())), None);
+
+        // A later save replaces the earlier one
+        store.save(&SavedState { tick: 4, ..saved }).unwrap();
+        assert_eq!(store.load().unwrap().unwrap().tick, 4);
+    }
+
+    #[test]
+    fn rejects_another_schema_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path().join("state.json"));
+        // The rest of an old file doesn't have to parse
+        fs::write(store.path(), r#"{"schema_version": 0, "ticks": "many"}"#).unwrap();
+
+        match store.load() {
+            Err(PersistError::Version { found, expected }) => {
+                assert_eq!(found, 0);
+                assert_eq!(expected, SCHEMA_VERSION);
+            }
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_a_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = StateStore::new(dir.path().join("state.json"));
+        fs::write(store.path(), "{\"schema_version\": 1, \"tick\"").unwrap();
+
+        assert!(matches!(store.load(), Err(PersistError::Format(_))));
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::client::types::{ArticleId, ListingId, Money};
+use crate::client::{ClientError, MarketSnapshot};
+
+use super::strategy::{Action, ActionOutcome};
+
+// Units customers bought from one of our listings
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sale {
+    pub listing_id: ListingId,
+    pub article_id: ArticleId,
+    pub count: usize,
+    // The price the listing had when the units were gone
+    pub unit_price: Money,
+}
+
+// One of our listings as it is after our own changes
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Tracked {
+    article_id: ArticleId,
+    count: usize,
+    price: Money,
+    // The price before our last update. Units that sold right before an update are hidden by
+    // it, they sold at this price.
+    replaced_price: Money,
+}
+
+// Tells our own listing changes from customer fills. Every count we set through
+// create_listing/update_listing is tracked, so any other change we observe was a customer.
+//
+// An update sets an absolute count. If customers bought from the listing after we last looked,
+// the update quietly puts the sold units back, taking them from our stock. Those sales only
+// show up as stock going missing, so the stock is tracked as well.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reconciler {
+    listings: HashMap<ListingId, Tracked>,
+    stock: HashMap<ArticleId, usize>,
+    // Nothing to compare the first observation with, it is taken as it is
+    initialized: bool,
+
+    // The fetches we looked at last, see MarketState
+    #[serde(skip)]
+    player_fetches: u64,
+    #[serde(skip)]
+    listings_fetches: u64,
+}
+
+impl Reconciler {
+    // Book the listing and stock changes of an action that went through
+    pub fn record(&mut self, action: &Action, outcome: &Result<ActionOutcome, ClientError>) {
+        match (action, outcome) {
+            (
+                Action::Buy {
+                    article_id, count, ..
+                },
+                Ok(_),
+            ) => {
+                *self.stock.entry(*article_id).or_default() += count;
+            }
+            (
+                Action::CreateListing {
+                    article_id,
+                    count,
+                    price_per_unit,
+                },
+                Ok(ActionOutcome::ListingCreated(listing_id)),
+            ) => {
+                self.take_stock(*article_id, *count);
+                self.listings.insert(
+                    *listing_id,
+                    Tracked {
+                        article_id: *article_id,
+                        count: *count,
+                        price: *price_per_unit,
+                        replaced_price: *price_per_unit,
+                    },
+                );
+            }
+            (
+                Action::UpdateListing {
+                    listing_id,
+                    count,
+                    price_per_unit,
+                },
+                Ok(_),
+            ) => {
+                let tracked = match self.listings.get_mut(listing_id) {
+                    Some(tracked) => tracked,
+                    None => {
+                        warn!(%listing_id, "Weird. Updated a listing we don't track");
+                        return;
+                    }
+                };
+                let (article_id, old_count) = (tracked.article_id, tracked.count);
+                tracked.count = *count;
+                tracked.replaced_price = tracked.price;
+                tracked.price = *price_per_unit;
+
+                // Listed units come from and go back to our stock
+                if *count > old_count {
+                    self.take_stock(article_id, count - old_count);
+                } else {
+                    *self.stock.entry(article_id).or_default() += old_count - count;
+                }
+            }
+            (Action::DeleteListing { listing_id }, Ok(_)) => {
+                if let Some(tracked) = self.listings.remove(listing_id) {
+                    *self.stock.entry(tracked.article_id).or_default() += tracked.count;
+                }
+            }
+            // Failed actions changed nothing
+            (_, _) => {}
+        }
+    }
+
+    // Compare our listings with what we expect them to be. Whatever is missing was bought by
+    // customers. Listings we already looked at predate our latest changes, so only freshly
+    // fetched ones count. The stock only counts if the player was fetched along with them.
+    pub fn observe(&mut self, snapshot: &MarketSnapshot) -> Vec<Sale> {
+        let fresh = snapshot.listings_fetches != self.listings_fetches;
+        let with_stock = fresh && snapshot.player_fetches != self.player_fetches;
+        self.listings_fetches = snapshot.listings_fetches;
+        self.player_fetches = snapshot.player_fetches;
+        if !fresh {
+            return Vec::new();
+        }
+
+        if !self.initialized {
+            if with_stock {
+                self.adopt(snapshot);
+            }
+            return Vec::new();
+        }
+
+        let mut sales = Vec::new();
+        let mut seen = HashSet::new();
+        for listing in snapshot.listings_of_player(snapshot.player.id) {
+            seen.insert(listing.id);
+
+            let tracked = match self.listings.get_mut(&listing.id) {
+                Some(tracked) => tracked,
+                None => {
+                    // Probably a create that went through although it looked failed to us
+                    info!(listing_id = %listing.id, "Found a listing we didn't know about");
+                    self.listings.insert(
+                        listing.id,
+                        Tracked {
+                            article_id: listing.article,
+                            count: listing.count,
+                            price: listing.price,
+                            replaced_price: listing.price,
+                        },
+                    );
+                    self.take_stock(listing.article, listing.count);
+                    continue;
+                }
+            };
+
+            if listing.count < tracked.count {
+                sales.push(Sale {
+                    listing_id: listing.id,
+                    article_id: tracked.article_id,
+                    count: tracked.count - listing.count,
+                    unit_price: tracked.price,
+                });
+            } else if listing.count > tracked.count {
+                // Same as above, an update that looked failed
+                info!(
+                    listing_id = %listing.id,
+                    expected = tracked.count,
+                    found = listing.count,
+                    "Listing has more units than we put in"
+                );
+                let (article_id, taken) = (tracked.article_id, listing.count - tracked.count);
+                self.take_stock(article_id, taken);
+            }
+
+            let tracked = self.listings.get_mut(&listing.id).unwrap();
+            tracked.count = listing.count;
+            tracked.price = listing.price;
+        }
+
+        // We never delete listings, so gone means sold out (or the game was reset, which
+        // discards the saved state anyway)
+        let gone = self
+            .listings
+            .keys()
+            .filter(|listing_id| !seen.contains(listing_id))
+            .copied()
+            .collect::<Vec<_>>();
+        for listing_id in gone {
+            let tracked = self.listings.remove(&listing_id).unwrap();
+            if tracked.count > 0 {
+                sales.push(Sale {
+                    listing_id,
+                    article_id: tracked.article_id,
+                    count: tracked.count,
+                    unit_price: tracked.price,
+                });
+            }
+        }
+
+        if with_stock {
+            sales.extend(self.observe_stock(snapshot));
+        }
+
+        for sale in &sales {
+            debug!(
+                listing_id = %sale.listing_id,
+                article_id = %sale.article_id,
+                count = sale.count,
+                price = %sale.unit_price,
+                "Customer fill"
+            );
+        }
+        sales
+    }
+
+    // Stock we don't have although we should went into a listing to cover units that sold
+    // right before we updated it
+    fn observe_stock(&mut self, snapshot: &MarketSnapshot) -> Vec<Sale> {
+        let observed = snapshot
+            .player
+            .stock
+            .iter()
+            .map(|stock| (stock.article_id, stock.stock))
+            .collect::<HashMap<_, _>>();
+
+        let mut sales = Vec::new();
+        for (article_id, expected) in &self.stock {
+            let found = observed.get(article_id).copied().unwrap_or(0);
+            if found >= *expected {
+                continue;
+            }
+            let listing = self
+                .listings
+                .iter()
+                .find(|(_, tracked)| tracked.article_id == *article_id);
+            match listing {
+                Some((listing_id, tracked)) => sales.push(Sale {
+                    listing_id: *listing_id,
+                    article_id: *article_id,
+                    count: expected - found,
+                    unit_price: tracked.replaced_price,
+                }),
+                None => {
+                    warn!(%article_id, expected, found, "Weird. Stock went missing");
+                }
+            }
+        }
+        for (article_id, found) in &observed {
+            let expected = self.stock.get(article_id).copied().unwrap_or(0);
+            if *found > expected {
+                // Probably a buy that went through although it looked failed to us
+                info!(%article_id, expected, found, "More stock than we bought");
+            }
+        }
+
+        self.stock = observed;
+        sales
+    }
+
+    fn adopt(&mut self, snapshot: &MarketSnapshot) {
+        self.listings = snapshot
+            .listings_of_player(snapshot.player.id)
+            .map(|listing| {
+                let tracked = Tracked {
+                    article_id: listing.article,
+                    count: listing.count,
+                    price: listing.price,
+                    replaced_price: listing.price,
+                };
+                (listing.id, tracked)
+            })
+            .collect();
+        self.stock = snapshot
+            .player
+            .stock
+            .iter()
+            .map(|stock| (stock.article_id, stock.stock))
+            .collect();
+        self.initialized = true;
+    }
+
+    fn take_stock(&mut self, article_id: ArticleId, count: usize) {
+        let stock = self.stock.entry(article_id).or_default();
+        *stock = stock.saturating_sub(count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::client::types::{ArticleId, SupplierId};
+    use crate::client::MarketState;
+    use crate::game::testing::{TestMarket, PLAYER};
+
+    // We hold 5 units of article 1 and list 2 more at 20
+    struct Game {
+        market: TestMarket,
+        state: MarketState,
+        reconciler: Reconciler,
+    }
+
+    impl Game {
+        fn start() -> Self {
+            let mut game = Self {
+                market: TestMarket::default()
+                    .article(1, &[], 10.0)
+                    .stock(1, 5)
+                    .listing(1, PLAYER, 1, 2, 20.0),
+                state: MarketState::default(),
+                reconciler: Reconciler::default(),
+            };
+            assert!(game.observe().is_empty());
+            game
+        }
+
+        fn observe(&mut self) -> Vec<Sale> {
+            self.market.fetch_into(&mut self.state);
+            let snapshot = MarketSnapshot::capture(&self.state, 1, Duration::ZERO);
+            self.reconciler.observe(&snapshot)
+        }
+
+        fn set_listing(&mut self, count: usize, price: f64) {
+            let listing = &mut self.market.listings[0];
+            listing.count = count;
+            listing.price = Money::from_f64(price);
+        }
+
+        fn set_stock(&mut self, count: usize) {
+            self.market.player.stock[0].stock = count;
+        }
+    }
+
+    fn update(count: usize, price: f64) -> Action {
+        Action::UpdateListing {
+            listing_id: ListingId(1),
+            count,
+            price_per_unit: Money::from_f64(price),
+        }
+    }
+
+    fn buy(count: usize) -> Action {
+        Action::Buy {
+            supplier_id: SupplierId(1),
+            article_id: ArticleId(1),
+            count,
+            price_per_unit: Money::from_f64(10.0),
+        }
+    }
+
+    fn rejected() -> Result<ActionOutcome, ClientError> {
+        Err(ClientError::Status {
+            endpoint: "/supplier/1/article/1/buy".to_string(),
+            status: StatusCode::BAD_REQUEST,
+            body: String::new(),
+        })
+    }
+
+    fn sale(count: usize, unit_price: f64) -> Sale {
+        Sale {
+            listing_id: ListingId(1),
+            article_id: ArticleId(1),
+            count,
+            unit_price: Money::from_f64(unit_price),
+        }
+    }
+
+    #[test]
+    fn finds_fills_in_listing_counts() {
+        let mut game = Game::start();
+
+        game.set_listing(1, 20.0);
+        assert_eq!(game.observe(), vec![sale(1, 20.0)]);
+        // Seen once, booked once
+        assert!(game.observe().is_empty());
+    }
+
+    #[test]
+    fn finds_sold_out_listings() {
+        let mut game = Game::start();
+
+        game.market.listings.clear();
+        assert_eq!(game.observe(), vec![sale(2, 20.0)]);
+    }
+
+    #[test]
+    fn ignores_our_own_updates() {
+        let mut game = Game::start();
+
+        game.reconciler
+            .record(&update(5, 18.0), &Ok(ActionOutcome::Done));
+        game.set_listing(5, 18.0);
+        game.set_stock(2);
+        assert!(game.observe().is_empty());
+    }
+
+    #[test]
+    fn finds_fills_hidden_by_an_update() {
+        let mut game = Game::start();
+
+        // A customer bought a unit right before we raised the listing to 4 units. The server
+        // took 3 units from our stock instead of the 2 we expected.
+        game.reconciler
+            .record(&update(4, 25.0), &Ok(ActionOutcome::Done));
+        game.set_listing(4, 25.0);
+        game.set_stock(2);
+        assert_eq!(game.observe(), vec![sale(1, 20.0)]);
+    }
+
+    #[test]
+    fn books_nothing_for_a_buy_that_never_landed() {
+        let mut game = Game::start();
+
+        game.reconciler.record(&buy(3), &rejected());
+        assert!(game.observe().is_empty());
+
+        // Our stock is as it was, moving it into the listing isn't a sale
+        game.reconciler
+            .record(&update(7, 20.0), &Ok(ActionOutcome::Done));
+        game.set_listing(7, 20.0);
+        game.market.player.stock.clear();
+        assert!(game.observe().is_empty());
+    }
+
+    #[test]
+    fn adopts_stock_we_didnt_know_we_bought() {
+        let mut game = Game::start();
+
+        // The buy looked failed to us but went through
+        game.reconciler.record(&buy(3), &rejected());
+        game.set_stock(8);
+        assert!(game.observe().is_empty());
+
+        game.reconciler
+            .record(&update(10, 20.0), &Ok(ActionOutcome::Done));
+        game.set_listing(10, 20.0);
+        game.market.player.stock.clear();
+        assert!(game.observe().is_empty());
+    }
+
+    #[test]
+    fn waits_for_fresh_listings() {
+        let mut game = Game::start();
+        let stale = MarketSnapshot::capture(&game.state, 1, Duration::ZERO);
+
+        game.set_listing(0, 20.0);
+        assert!(game.reconciler.observe(&stale).is_empty());
+        assert_eq!(game.observe(), vec![sale(2, 20.0)]);
+    }
+}
@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{self, Interval, MissedTickBehavior};
+use tracing::{info, warn};
+
+use super::EngineConfig;
+
+// What to do with main ticks we missed because a tick took too long (or we stood down)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedTickPolicy {
+    // Forget about them and continue on the regular schedule
+    #[default]
+    Skip,
+    // Play them back to back until we are on schedule again
+    Burst,
+}
+
+// Drives the game loop: a main tick to play on and a fast watch tick in between to look at
+// the other players. Waiting never blocks the runtime.
+pub struct Scheduler {
+    tick_duration: Duration,
+    policy: MissedTickPolicy,
+    main: Interval,
+    watch: Interval,
+
+    pub missed_ticks: u64,
+    pub overruns: u64,
+}
+
+impl Scheduler {
+    pub fn new(config: &EngineConfig) -> Self {
+        let tick_duration = Duration::from_millis(config.tick_timer_in_ms);
+
+        let mut main = time::interval(tick_duration);
+        main.set_missed_tick_behavior(match config.missed_ticks {
+            MissedTickPolicy::Skip => MissedTickBehavior::Skip,
+            MissedTickPolicy::Burst => MissedTickBehavior::Burst,
+        });
+
+        // Looking at an old market twice in a row is pointless, so watches are always skipped
+        let mut watch = time::interval(Duration::from_millis(config.check_others_timer_in_ms));
+        watch.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        Self {
+            tick_duration,
+            policy: config.missed_ticks,
+            main,
+            watch,
+
+            missed_ticks: 0,
+            overruns: 0,
+        }
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    // Wait for the next main tick. The first one is right away.
+    pub async fn next_tick(&mut self) {
+        let scheduled = self.main.tick().await;
+
+        // Every full period we are late is a tick that should have happened by now
+        let late = time::Instant::now().saturating_duration_since(scheduled);
+        let missed = (late.as_millis() / self.tick_duration.as_millis()) as u64;
+        if missed > 0 {
+            match self.policy {
+                MissedTickPolicy::Skip => {
+                    self.missed_ticks += missed;
+                    info!(missed, "Skipped missed ticks");
+                }
+                // The others follow right after this one, each is counted when it comes
+                MissedTickPolicy::Burst => {
+                    self.missed_ticks += 1;
+                    info!(
+                        late_ms = late.as_millis() as u64,
+                        "Catching up on a missed tick"
+                    );
+                }
+            }
+        }
+
+        self.watch.reset();
+    }
+
+    // Wait for the next watch tick
+    pub async fn next_watch(&mut self) {
+        self.watch.tick().await;
+    }
+
+    // Called once the work of a tick is done, so slow ticks show up in the logs. Returns how
+    // long the tick took.
+    pub fn report(&mut self, tick: u64, started: Instant, now: Instant) -> Duration {
+        let took = now.saturating_duration_since(started);
+        if took > self.tick_duration {
+            self.overruns += 1;
+            warn!(
+                tick,
+                overrun_ms = (took - self.tick_duration).as_millis() as u64,
+                overruns = self.overruns,
+                "Tick overran"
+            );
+        }
+        took
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(100);
+
+    fn scheduler(missed_ticks: MissedTickPolicy) -> Scheduler {
+        Scheduler::new(&EngineConfig {
+            tick_timer_in_ms: TICK.as_millis() as u64,
+            missed_ticks,
+            ..EngineConfig::default()
+        })
+    }
+
+    // Plays the first tick, then stalls for 3.5 ticks and plays until the tick at 400ms
+    async fn stall(scheduler: &mut Scheduler) -> u64 {
+        let start = time::Instant::now();
+        scheduler.next_tick().await;
+        time::advance(TICK * 7 / 2).await;
+
+        let mut played = 0;
+        while time::Instant::now() < start + TICK * 4 {
+            scheduler.next_tick().await;
+            played += 1;
+        }
+        played
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_skipped_ticks() {
+        let mut scheduler = scheduler(MissedTickPolicy::Skip);
+
+        // The tick at 100ms is played late, 200ms and 300ms are skipped
+        assert_eq!(stall(&mut scheduler).await, 2);
+        assert_eq!(scheduler.missed_ticks, 2);
+
+        // Back on schedule
+        scheduler.next_tick().await;
+        assert_eq!(scheduler.missed_ticks, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_ticks_played_in_a_burst() {
+        let mut scheduler = scheduler(MissedTickPolicy::Burst);
+
+        // The ticks at 100ms, 200ms and 300ms are played back to back
+        assert_eq!(stall(&mut scheduler).await, 4);
+        assert_eq!(scheduler.missed_ticks, 2);
+
+        scheduler.next_tick().await;
+        assert_eq!(scheduler.missed_ticks, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn misses_nothing_on_schedule() {
+        let mut scheduler = scheduler(MissedTickPolicy::Skip);
+
+        for _ in 0..5 {
+            scheduler.next_tick().await;
+            time::advance(TICK / 2).await;
+        }
+        assert_eq!(scheduler.missed_ticks, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counts_ticks_that_take_too_long() {
+        let mut scheduler = scheduler(MissedTickPolicy::Skip);
+        let started = Instant::now();
+
+        assert_eq!(scheduler.report(1, started, started + TICK / 2), TICK / 2);
+        assert_eq!(scheduler.report(2, started, started + TICK), TICK);
+        assert_eq!(scheduler.overruns, 0);
+
+        assert_eq!(scheduler.report(3, started, started + TICK * 3), TICK * 3);
+        assert_eq!(scheduler.overruns, 1);
+        // A clock that went backwards doesn't count
+        assert_eq!(scheduler.report(4, started + TICK, started), Duration::ZERO);
+        assert_eq!(scheduler.overruns, 1);
+    }
+}
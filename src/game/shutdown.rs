@@ -0,0 +1,264 @@
+use std::future;
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::client::types::Money;
+use crate::client::{MarketSnapshot, MarketState};
+
+use super::Action;
+
+// Tells the game loop to wind down. Once requested, no more buying happens and the loop
+// stops after the tick in flight.
+#[derive(Clone)]
+pub struct Shutdown {
+    requested: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    // Requested on SIGINT or SIGTERM. A second signal quits right away.
+    pub fn on_signals() -> Self {
+        let (sender, requested) = watch::channel(false);
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            info!("Shutting down after this tick, signal again to quit right away");
+            let _ = sender.send(true);
+
+            wait_for_signal().await;
+            std::process::exit(130);
+        });
+        Self { requested }
+    }
+
+    pub fn never() -> Self {
+        let (_, requested) = watch::channel(false);
+        Self { requested }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    // Completes once a shutdown is requested, never if nobody can request one
+    pub async fn requested(&mut self) {
+        while !*self.requested.borrow() {
+            if self.requested.changed().await.is_err() {
+                future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!(error = %e, "Couldn't listen for SIGTERM");
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!(error = %e, "Couldn't listen for Ctrl-C");
+        future::pending::<()>().await;
+    }
+}
+
+// Make our inventory sell before we leave: listings above the average supplier price times
+// the multiplier are lowered to it, stock we still hold is listed at that price
+pub fn liquidation(snapshot: &MarketSnapshot, price_multiplier: f64) -> Vec<Action> {
+    let mut actions = Vec::new();
+
+    let target_price = |article_id| {
+        let price = snapshot
+            .article_price_history
+            .get(&article_id)
+            .map(|history| history.average_price() * price_multiplier);
+        if price.is_none() {
+            warn!(%article_id, "Weird. Didn't find an article price history");
+        }
+        price
+    };
+
+    // Empty listings don't sell anything anyway
+    let own_listings = snapshot
+        .listings_of_player(snapshot.player.id)
+        .filter(|listing| listing.count > 0)
+        .collect::<Vec<_>>();
+
+    for listing in &own_listings {
+        // Stock of the same article goes into its first listing, the others are repriced only
+        let first = own_listings
+            .iter()
+            .find(|first| first.article == listing.article)
+            .is_some_and(|first| first.id == listing.id);
+        let unlisted = if first {
+            snapshot
+                .player
+                .stock
+                .iter()
+                .find(|stock| stock.article_id == listing.article)
+                .map_or(0, |stock| stock.stock)
+        } else {
+            0
+        };
+
+        let price = match target_price(listing.article) {
+            Some(target_price) => listing.price.min(target_price),
+            None => listing.price,
+        };
+        if price != listing.price || unlisted > 0 {
+            actions.push(Action::UpdateListing {
+                listing_id: listing.id,
+                count: listing.count + unlisted,
+                price_per_unit: price,
+            });
+        }
+    }
+
+    for stock in &snapshot.player.stock {
+        if stock.stock == 0
+            || own_listings
+                .iter()
+                .any(|listing| listing.article == stock.article_id)
+        {
+            continue;
+        }
+        if let Some(price) = target_price(stock.article_id) {
+            actions.push(Action::CreateListing {
+                article_id: stock.article_id,
+                count: stock.stock,
+                price_per_unit: price,
+            });
+        }
+    }
+
+    actions
+}
+
+// What we leave behind at the end of a session
+pub struct SessionSummary {
+    pub ticks: u64,
+    pub money: Money,
+    pub piggybank: Option<Money>,
+    pub units_in_stock: usize,
+    pub units_listed: usize,
+    // Stock and listed units, valued at the average supplier price
+    pub inventory_value: Money,
+}
+
+impl SessionSummary {
+    pub fn capture(state: &MarketState, ticks: u64, piggybank: Option<Money>) -> Self {
+        let stock = state
+            .player
+            .stock
+            .iter()
+            .map(|stock| (stock.article_id, stock.stock))
+            .collect::<Vec<_>>();
+        let listed = state
+            .index()
+            .listings_of_player(&state.listings, state.player.id)
+            .map(|listing| (listing.article, listing.count))
+            .collect::<Vec<_>>();
+
+        let inventory_value = stock
+            .iter()
+            .chain(listed.iter())
+            .map(
+                |(article_id, count)| match state.article_price_history.get(article_id) {
+                    Some(history) => history.average_price() * *count,
+                    None => Money::ZERO,
+                },
+            )
+            .sum();
+
+        Self {
+            ticks,
+            money: state.player.money,
+            piggybank,
+            units_in_stock: stock.iter().map(|(_, count)| count).sum(),
+            units_listed: listed.iter().map(|(_, count)| count).sum(),
+            inventory_value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::types::{ArticleId, ListingId};
+    use crate::game::testing::{TestMarket, OTHER_PLAYER, PLAYER};
+
+    #[test]
+    fn lowers_listings_to_the_target_price() {
+        let snapshot = TestMarket::default()
+            .article(1, &[], 10.0)
+            .listing(1, PLAYER, 1, 2, 30.0)
+            .listing(2, OTHER_PLAYER, 1, 5, 30.0)
+            .snapshot();
+
+        assert_eq!(
+            liquidation(&snapshot, 1.5),
+            vec![Action::UpdateListing {
+                listing_id: ListingId(1),
+                count: 2,
+                price_per_unit: Money::from_f64(15.0),
+            }]
+        );
+    }
+
+    #[test]
+    fn leaves_cheaper_and_empty_listings_alone() {
+        let snapshot = TestMarket::default()
+            .article(1, &[], 10.0)
+            .listing(1, PLAYER, 1, 2, 8.0)
+            .listing(2, PLAYER, 1, 0, 30.0)
+            .snapshot();
+
+        assert!(liquidation(&snapshot, 1.0).is_empty());
+    }
+
+    #[test]
+    fn lists_unlisted_stock_once_per_article() {
+        let snapshot = TestMarket::default()
+            .article(1, &[], 10.0)
+            .article(2, &[], 20.0)
+            .stock(1, 4)
+            .stock(2, 3)
+            .listing(1, PLAYER, 1, 2, 30.0)
+            .listing(2, PLAYER, 1, 1, 25.0)
+            .snapshot();
+
+        assert_eq!(
+            liquidation(&snapshot, 1.0),
+            vec![
+                Action::UpdateListing {
+                    listing_id: ListingId(1),
+                    count: 6,
+                    price_per_unit: Money::from_f64(10.0),
+                },
+                Action::UpdateListing {
+                    listing_id: ListingId(2),
+                    count: 1,
+                    price_per_unit: Money::from_f64(10.0),
+                },
+                Action::CreateListing {
+                    article_id: ArticleId(2),
+                    count: 3,
+                    price_per_unit: Money::from_f64(20.0),
+                },
+            ]
+        );
+    }
+}
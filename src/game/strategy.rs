@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::types::{ArticleId, ListingId, Money, SupplierId, TagId};
+use crate::client::{ClientError, MarketSnapshot};
+
+use super::reconcile::Sale;
+
+// Something a strategy wants the engine to do on the market
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Buy {
+        supplier_id: SupplierId,
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    },
+    CreateListing {
+        article_id: ArticleId,
+        count: usize,
+        price_per_unit: Money,
+    },
+    UpdateListing {
+        listing_id: ListingId,
+        count: usize,
+        price_per_unit: Money,
+    },
+    DeleteListing {
+        listing_id: ListingId,
+    },
+}
+
+// Which strategy the bot plays
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyKind {
+    #[default]
+    TagTrend,
+    Bandit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionOutcome {
+    Done,
+    ListingCreated(ListingId),
+}
+
+// A strategy only decides, the engine fetches market data and executes the returned actions.
+// Every tick runs on_tick, executes its actions, refreshes our player and listings and runs
+// on_restock. Until the next tick, on_watch is called with fresh listings over and over.
+// Sales are only found when the strategy runs inside the Bookkeeper.
+pub trait Strategy {
+    fn name(&self) -> &str;
+
+    // The market right before the first tick
+    fn on_start(&mut self, _snapshot: &MarketSnapshot) {}
+
+    // Repricing and buying
+    fn on_tick(&mut self, snapshot: &MarketSnapshot) -> Vec<Action>;
+
+    // Listing what we bought
+    fn on_restock(&mut self, _snapshot: &MarketSnapshot) -> Vec<Action> {
+        Vec::new()
+    }
+
+    // The market after all actions of this tick went through
+    fn on_tick_end(&mut self, _snapshot: &MarketSnapshot) {}
+
+    // Reacting to other players between ticks
+    fn on_watch(&mut self, _snapshot: &MarketSnapshot) -> Vec<Action> {
+        Vec::new()
+    }
+
+    // Called for every action the engine executed, successful or not
+    fn on_outcome(&mut self, _action: &Action, _outcome: &Result<ActionOutcome, ClientError>) {}
+
+    // What customers bought from us since the last call, told apart from our own listing
+    // changes. Called right before the hook that gets to see the market they were found in.
+    fn on_sales(&mut self, _sales: &[Sale]) {}
+
+    // Whatever should survive a restart, saved by the engine after every tick
+    fn save_state(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    // Gets what save_state returned in the previous run, before on_start is called
+    fn restore_state(&mut self, _state: serde_json::Value) -> Result<(), serde_json::Error> {
+        Ok(())
+    }
+
+    // Money the strategy keeps aside and won't spend, for the session summary
+    fn piggybank(&self) -> Option<Money> {
+        None
+    }
+
+    // How much the strategy thinks every tag is in demand, for the metrics
+    fn tag_trends(&self) -> Option<BTreeMap<TagId, f64>> {
+        None
+    }
+}
@@ -0,0 +1,699 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::client::types::{ArticleId, Listing, ListingId, Money, Player, TagId};
+use crate::client::{ClientError, MarketSnapshot};
+
+use super::budget::{plan_purchases, Offer, Purchase, Wanted};
+use super::config::{DemandConfig, TagTrendConfig};
+use super::demand::DemandModel;
+use super::reconcile::Sale;
+use super::strategy::{Action, ActionOutcome, Strategy};
+use super::trend::TagTrends;
+
+// Battleplan:
+
+// Loop every 30s
+
+// What are our limits?
+// --- Only buy when the price is below the average + 10%
+// --- Store half of any winnings in a piggybank, never touch it
+
+// If an article didn't sell:
+// --- Reduce it's price by PRICE_INCREASE
+// ------ Up to the average
+// --------- Kill this rule at the end, so we can sell our stock.
+// --- Reduce it's tag level to 0
+
+// If an article did sell:
+// --- Increase it's price by PRICE_REDUCTION
+// --- Increase it's tag level by TAG_LEVEL_INCREASE
+// --- Increase it's similar tags by SIMILAR_TAG_LEVEL_INCREASE
+
+// If an article has a tag level > 0:
+// --- Buy an amount that is equal to the tag level
+// ------ Kill this rule at the end, so we can sell our stock.
+
+// Try to keep one of every article in stock
+// --- Start selling for 200% or the current price
+// --- Kill this rule at the end, so we can sell our stock.
+
+// Bedazzle other users
+// --- Old listings with a count of 0 will receive a update to hundred times its price
+// --- Create some (100?) new listings with a count of 0 and a weird price (even negatives!)
+
+// Meantime:
+// --- Don't hibernate, look at other players' listings
+// --- Make sure we offer stuff at a lower price than they do (but not lower than average * multiplier)
+
+pub struct TagTrendStrategy {
+    config: TagTrendConfig,
+    rng: StdRng,
+
+    piggybank: Money,
+    old_player: Option<Player>,
+    // Units sold per listing since we last repriced
+    sold: HashMap<ListingId, usize>,
+    pub demand: DemandModel,
+
+    pub tag_trends: TagTrends,
+    pub bedazzlement_listings: Vec<ListingId>,
+}
+
+// The part of TagTrendStrategy that survives a restart
+#[derive(Serialize, Deserialize)]
+struct TagTrendState {
+    piggybank: Money,
+    old_player: Option<Player>,
+    #[serde(default)]
+    sold: HashMap<ListingId, usize>,
+    #[serde(default)]
+    demand: DemandModel,
+    #[serde(default)]
+    tag_trends: TagTrends,
+    bedazzlement_listings: Vec<ListingId>,
+}
+
+impl Default for TagTrendStrategy {
+    fn default() -> Self {
+        Self::new(TagTrendConfig::default(), DemandConfig::default())
+    }
+}
+
+impl TagTrendStrategy {
+    pub fn new(config: TagTrendConfig, demand: DemandConfig) -> Self {
+        Self {
+            config,
+            rng: StdRng::from_entropy(),
+
+            piggybank: Money::ZERO,
+            old_player: None,
+            sold: HashMap::new(),
+            demand: DemandModel::new(demand),
+
+            tag_trends: TagTrends::default(),
+            bedazzlement_listings: Vec::new(),
+        }
+    }
+
+    fn remember(&mut self, snapshot: &MarketSnapshot) {
+        self.old_player = Some(snapshot.player.clone());
+    }
+
+    fn update_piggybank(&mut self, snapshot: &MarketSnapshot) {
+        let old_money = match &self.old_player {
+            Some(old_player) => old_player.money,
+            None => snapshot.player.money,
+        };
+
+        info!(
+            money = %snapshot.player.money,
+            earned = %(snapshot.player.money - old_money),
+            "Player money"
+        );
+
+        let earnings = snapshot.player.money - old_money;
+        // If we have some buffer…
+        if snapshot.player.money - self.piggybank > Money::from_f64(500.0) && earnings.is_positive()
+        {
+            // …put some of our earnings in our virtual piggybank
+            let piggy_money = earnings / self.config.piggybank_divider;
+            self.piggybank += piggy_money;
+            info!(piggybank = %self.piggybank, added = %piggy_money, "Piggybank");
+        } else {
+            info!(piggybank = %self.piggybank, "Piggybank");
+        }
+    }
+
+    fn print_portfolio(&self, snapshot: &MarketSnapshot, own_listings: &[Listing]) {
+        let portfolio = snapshot.portfolio(own_listings);
+        info!(
+            items = portfolio.items,
+            min_value = %portfolio.min_value,
+            max_value = %portfolio.max_value,
+            "Portfolio"
+        );
+    }
+
+    // Did we sell anything?
+    fn reprice(&mut self, snapshot: &MarketSnapshot, own_listings: &[Listing]) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        for listing in own_listings {
+            // get article price history
+            let article_price_history = match snapshot.article_price_history.get(&listing.article) {
+                Some(history) => history,
+                None => {
+                    warn!(article_id = %listing.article, "Weird. Didn't find an article price history");
+                    continue; // Ideally this never happens
+                }
+            };
+            let article_average_price = article_price_history.average_price();
+
+            let sell_count = self.sold.remove(&listing.id).unwrap_or(0);
+
+            if sell_count == 0 {
+                // Article didn't sell at all, its tags fade on their own
+
+                if let Some(new_price) = self.demand_price(snapshot, listing, article_average_price)
+                {
+                    actions.push(Action::UpdateListing {
+                        listing_id: listing.id,
+                        count: listing.count,
+                        price_per_unit: new_price,
+                    });
+                    continue;
+                }
+
+                // Reduce price down to the average
+                let lowered_price = listing.price * (1.0 - self.config.price_reduction);
+                let low_average_selling_price =
+                    article_average_price * self.config.low_average_price_selling_multiplier;
+                let new_price = if lowered_price > low_average_selling_price
+                    || snapshot.elapsed.as_secs() > self.config.sell_below_average_after_seconds
+                {
+                    lowered_price
+                } else {
+                    low_average_selling_price
+                };
+
+                actions.push(Action::UpdateListing {
+                    listing_id: listing.id,
+                    count: listing.count,
+                    price_per_unit: new_price,
+                });
+            } else {
+                // Article did sell
+                info!(
+                    article_id = %listing.article,
+                    count = sell_count,
+                    price = %listing.price,
+                    "Sold articles"
+                );
+
+                // Its tags and the similar ones are trending
+                self.tag_trends.record_sale(
+                    snapshot,
+                    listing.article,
+                    sell_count,
+                    listing.price,
+                    &self.config.trend_settings(),
+                );
+
+                // Increase price
+                let new_price = self
+                    .demand_price(snapshot, listing, article_average_price)
+                    .unwrap_or(listing.price * (1.0 + self.config.price_increase));
+
+                actions.push(Action::UpdateListing {
+                    listing_id: listing.id,
+                    count: listing.count,
+                    price_per_unit: new_price,
+                });
+            }
+        }
+        // Sales of listings we don't reprice (bedazzlement) don't matter
+        self.sold.clear();
+
+        actions
+    }
+
+    // The best price by the demand curve, but not below the average price as long as we keep
+    // that rule. None without demand pricing or a curve for the article.
+    fn demand_price(
+        &self,
+        snapshot: &MarketSnapshot,
+        listing: &Listing,
+        article_average_price: Money,
+    ) -> Option<Money> {
+        if !self.config.demand_pricing {
+            return None;
+        }
+        let best_price = self.demand.best_price(snapshot, listing.article)?;
+
+        let low_average_selling_price =
+            article_average_price * self.config.low_average_price_selling_multiplier;
+        let new_price = if best_price > low_average_selling_price
+            || snapshot.elapsed.as_secs() > self.config.sell_below_average_after_seconds
+        {
+            best_price
+        } else {
+            low_average_selling_price
+        };
+        debug!(
+            article_id = %listing.article,
+            old_price = %listing.price,
+            new_price = %new_price,
+            "Demand price"
+        );
+        Some(new_price)
+    }
+
+    // Let's go shopping!
+    fn shop(&self, snapshot: &MarketSnapshot, own_listings: &[Listing]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let mut articles_to_buy = HashMap::new();
+
+        // Buy articles according to trending tags
+        if snapshot.elapsed.as_secs() < self.config.dont_buy_based_on_tag_level_after_seconds {
+            for (trending_tag, score) in self
+                .tag_trends
+                .scores()
+                .into_iter()
+                .filter(|(_, score)| *score >= self.config.tag_level_buy_threshold)
+            {
+                // One unit per full point of the score
+                for article in snapshot.articles_with_tag(trending_tag) {
+                    let article_count = articles_to_buy.entry(article.id).or_insert(0);
+                    *article_count += score as usize;
+                }
+            }
+        }
+
+        // Buy at least one of every article if we don't have it already
+        if snapshot.elapsed.as_secs() < self.config.dont_buy_one_of_everything_after_seconds {
+            for article in snapshot.articles.iter() {
+                // Not in our stock
+                if !snapshot
+                    .player
+                    .stock
+                    .iter()
+                    .any(|player_stock| player_stock.article_id == article.id)
+                {
+                    // Not in our listings with a count > 0
+                    if !own_listings
+                        .iter()
+                        .any(|listing| listing.article == article.id && listing.count > 0)
+                    {
+                        articles_to_buy.entry(article.id).or_insert(1);
+                    }
+                }
+            }
+        }
+
+        // Limit our purchasing power
+        let available_money = snapshot.player.money - self.piggybank;
+
+        let mut wanted = Vec::new();
+        let mut offers = Vec::new();
+        for (article_id, count) in articles_to_buy {
+            let article_price_history = match snapshot.article_price_history.get(&article_id) {
+                Some(history) => history,
+                None => continue, // No supplier ever had it
+            };
+            let article_average_price = article_price_history.average_price();
+
+            // We'll sell it at least for the low average selling price, unless the demand
+            // curve knows better
+            let expected_price = self
+                .demand
+                .best_price(snapshot, article_id)
+                .filter(|_| self.config.demand_pricing)
+                .unwrap_or(
+                    article_average_price * self.config.low_average_price_selling_multiplier,
+                );
+            wanted.push(Wanted {
+                article_id,
+                count,
+                expected_price,
+            });
+
+            // Check if the price is sane
+            let max_price = article_average_price * self.config.average_price_buying_multiplier;
+            offers.extend(
+                snapshot
+                    .suppliers_by_price(article_id)
+                    .into_iter()
+                    .filter(|(_, stock)| stock.price <= max_price)
+                    .map(|(supplier_id, stock)| Offer {
+                        supplier_id,
+                        article_id,
+                        available: stock.stock,
+                        unit_price: stock.price,
+                    }),
+            );
+        }
+
+        let purchases = plan_purchases(available_money, &wanted, &offers);
+        info!(
+            budget = %available_money,
+            spent = %purchases.iter().map(Purchase::cost).sum::<Money>(),
+            units = purchases.iter().map(|purchase| purchase.count).sum::<usize>(),
+            "Shopping plan"
+        );
+        for purchase in purchases {
+            // We don't know yet if the buy works out, the plan assumes it does
+            actions.push(Action::Buy {
+                supplier_id: purchase.supplier_id,
+                article_id: purchase.article_id,
+                count: purchase.count,
+                price_per_unit: purchase.unit_price,
+            });
+        }
+
+        actions
+    }
+}
+
+impl Strategy for TagTrendStrategy {
+    fn name(&self) -> &str {
+        "tag-trend"
+    }
+
+    fn on_start(&mut self, snapshot: &MarketSnapshot) {
+        // After a restart we keep the restored data, so we notice what sold while we were gone
+        if self.old_player.is_none() {
+            self.remember(snapshot);
+        }
+    }
+
+    fn on_tick(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        // Trends fade with game time, whether we play the tick or not
+        self.tag_trends
+            .decay_to(snapshot.elapsed, &self.config.trend_settings());
+
+        // Stale listings would look like nothing sold, stale suppliers like nothing changed
+        if !snapshot.fetch.is_complete() {
+            info!("Market data incomplete, sitting this tick out");
+            // Our prices stay as they are, customers keep buying at them
+            self.demand.carry_over();
+            return Vec::new();
+        }
+
+        // Whatever sold since the last tick is in, learn from it before repricing
+        self.demand.close();
+
+        self.update_piggybank(snapshot);
+
+        let own_listings = snapshot.get_own_listings(&self.bedazzlement_listings);
+        self.print_portfolio(snapshot, &own_listings);
+
+        let mut actions = self.reprice(snapshot, &own_listings);
+        let top = self
+            .tag_trends
+            .top(3)
+            .iter()
+            .map(|(tag_id, score)| format!("{}={:.2}", tag_id, score))
+            .collect::<Vec<_>>();
+        info!(top = %top.join(", "), "Trending tags");
+        // Customers see the new prices until the next tick
+        self.demand
+            .open(snapshot, &repriced(&own_listings, &actions));
+        actions.extend(self.shop(snapshot, &own_listings));
+        actions
+    }
+
+    fn on_restock(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        // Bedazzle other users
+        if snapshot.elapsed.as_secs() > self.config.bedazzle_after_seconds {
+            // Listings with a count of 0 will receive a price update to hundred times the average
+            for listing in snapshot
+                .get_own_listings(&self.bedazzlement_listings)
+                .iter()
+                .filter(|listing| listing.count == 0)
+            {
+                actions.push(Action::UpdateListing {
+                    listing_id: listing.id,
+                    count: 0,
+                    price_per_unit: listing.price * 100.0,
+                });
+                self.bedazzlement_listings.push(listing.id);
+            }
+            // Create some new listings with a count of 0 and a negative price
+            for stock in snapshot.player.stock.iter() {
+                let random_price = Money::from_f64(self.rng.gen_range(-1000.0..=0.0));
+                actions.push(Action::CreateListing {
+                    article_id: stock.article_id,
+                    count: 0,
+                    price_per_unit: random_price,
+                });
+            }
+        }
+
+        // Move whole stock to listings
+        for stock in snapshot.player.stock.iter() {
+            let listing = snapshot
+                .listings_for_article(stock.article_id)
+                .find(|listing| {
+                    listing.player == snapshot.player.id
+                        && !self.bedazzlement_listings.contains(&listing.id)
+                });
+
+            if let Some(listing) = listing {
+                // Update existing listing
+                actions.push(Action::UpdateListing {
+                    listing_id: listing.id,
+                    count: listing.count + stock.stock,
+                    price_per_unit: listing.price,
+                });
+            } else {
+                // Create a new listing
+
+                let article_price_history = match snapshot
+                    .article_price_history
+                    .get(&stock.article_id)
+                {
+                    Some(history) => history,
+                    None => {
+                        warn!(article_id = %stock.article_id, "Weird. Didn't find an article price history");
+                        continue; // Ideally this never happens
+                    }
+                };
+                let article_average_price = article_price_history.average_price();
+
+                actions.push(Action::CreateListing {
+                    article_id: stock.article_id,
+                    count: stock.stock,
+                    price_per_unit: article_average_price
+                        * self.config.high_average_price_selling_multiplier,
+                });
+            }
+        }
+
+        actions
+    }
+
+    fn on_tick_end(&mut self, snapshot: &MarketSnapshot) {
+        // Store data for next tick
+        self.remember(snapshot);
+    }
+
+    fn on_watch(&mut self, snapshot: &MarketSnapshot) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        let own_listings = snapshot.get_own_listings(&self.bedazzlement_listings);
+        let other_listings = snapshot.get_other_listings(&self.bedazzlement_listings);
+
+        let mut lowest_other_article_prices: HashMap<ArticleId, Money> = HashMap::new();
+
+        // get lower article price than other_listings or the average price with multiplier
+        for other_listing in other_listings {
+            let lower_other_price = other_listing.price * (1.0 - self.config.price_reduction);
+
+            let article_price_history = match snapshot
+                .article_price_history
+                .get(&other_listing.article)
+            {
+                Some(history) => history,
+                None => {
+                    warn!(article_id = %other_listing.article, "Weird. Didn't find an article price history");
+                    continue; // Ideally this never happens
+                }
+            };
+            let article_average_price = article_price_history.average_price();
+            let low_average_selling_price =
+                article_average_price * self.config.low_average_price_selling_multiplier;
+
+            let lowest_possible_price = if lower_other_price > low_average_selling_price
+                || snapshot.elapsed.as_secs() > self.config.sell_below_average_after_seconds
+            {
+                lower_other_price
+            } else {
+                low_average_selling_price
+            };
+
+            lowest_other_article_prices
+                .entry(other_listing.article)
+                .and_modify(|price| {
+                    if *price > lowest_possible_price {
+                        *price = lowest_possible_price;
+                    }
+                })
+                .or_insert(lowest_possible_price);
+        }
+
+        // Lower our own listings accordingly so we can sell them
+        for listing in own_listings {
+            if let Some(adjusted_other_price) = lowest_other_article_prices.get(&listing.article) {
+                if *adjusted_other_price < listing.price {
+                    if listing.price - *adjusted_other_price < Money::from_micros(10) {
+                        // Don't spam the server, the price difference is way too small
+                        continue;
+                    }
+
+                    actions.push(Action::UpdateListing {
+                        listing_id: listing.id,
+                        count: listing.count,
+                        price_per_unit: *adjusted_other_price,
+                    });
+                }
+            }
+        }
+
+        actions
+    }
+
+    fn on_outcome(&mut self, action: &Action, outcome: &Result<ActionOutcome, ClientError>) {
+        // The only empty listings we create are for bedazzlement
+        if let (
+            Action::CreateListing { count: 0, .. },
+            Ok(ActionOutcome::ListingCreated(listing_id)),
+        ) = (action, outcome)
+        {
+            self.bedazzlement_listings.push(*listing_id);
+        }
+    }
+
+    fn on_sales(&mut self, sales: &[Sale]) {
+        for sale in sales {
+            *self.sold.entry(sale.listing_id).or_default() += sale.count;
+        }
+        self.demand.record_sales(sales);
+    }
+
+    fn save_state(&self) -> Option<serde_json::Value> {
+        let state = TagTrendState {
+            piggybank: self.piggybank,
+            old_player: self.old_player.clone(),
+            sold: self.sold.clone(),
+            demand: self.demand.clone(),
+            tag_trends: self.tag_trends.clone(),
+            bedazzlement_listings: self.bedazzlement_listings.clone(),
+        };
+        serde_json::to_value(state).ok()
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> Result<(), serde_json::Error> {
+        let state: TagTrendState = serde_json::from_value(state)?;
+
+        self.piggybank = state.piggybank;
+        self.old_player = state.old_player;
+        self.sold = state.sold;
+        self.demand.restore(state.demand);
+        self.tag_trends = state.tag_trends;
+        self.bedazzlement_listings = state.bedazzlement_listings;
+
+        Ok(())
+    }
+
+    fn piggybank(&self) -> Option<Money> {
+        Some(self.piggybank)
+    }
+
+    fn tag_trends(&self) -> Option<BTreeMap<TagId, f64>> {
+        Some(self.tag_trends.scores())
+    }
+}
+
+// Our listings with the prices of the updates
+fn repriced(own_listings: &[Listing], actions: &[Action]) -> Vec<Listing> {
+    let mut listings = own_listings.to_vec();
+    for action in actions {
+        if let Action::UpdateListing {
+            listing_id,
+            price_per_unit,
+            ..
+        } = action
+        {
+            if let Some(listing) = listings
+                .iter_mut()
+                .find(|listing| listing.id == *listing_id)
+            {
+                listing.price = *price_per_unit;
+            }
+        }
+    }
+    listings
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::client::memory::{MemoryClient, MemoryMarket};
+    use crate::client::types::SupplierId;
+    use crate::client::MarketApi;
+    use crate::game::testing::{TestMarket, PLAYER};
+    use crate::game::{self, Bookkeeper, Shutdown};
+    use crate::metrics::Metrics;
+
+    #[tokio::test]
+    async fn splits_purchases_across_suppliers_cheapest_first() {
+        // Supplier 1 has 3 units at 12, supplier 2 has 2 at 10 and supplier 3 none at 8
+        let market = TestMarket::default()
+            .tag(1, &[])
+            .article(1, &[1], 12.0)
+            .supplier(1, 1, 3, 12.0)
+            .supplier(2, 1, 2, 10.0)
+            .supplier(3, 1, 0, 8.0);
+
+        let config = TagTrendConfig {
+            average_price_buying_multiplier: 2.0,
+            low_average_price_selling_multiplier: 3.0,
+            ..TagTrendConfig::default()
+        };
+        let mut strategy = TagTrendStrategy::new(config, DemandConfig::default());
+        // A trend score of 4 for the tag of the article, so we want 4 units
+        strategy.tag_trends.record_sale(
+            &market.snapshot(),
+            ArticleId(1),
+            8,
+            Money::from_f64(8.0),
+            &strategy.config.trend_settings(),
+        );
+
+        let mut memory_market = MemoryMarket::new(market.articles, market.tags, market.suppliers);
+        assert_eq!(memory_market.add_player(Money::from_f64(1000.0)), PLAYER);
+        let mut client = MemoryClient::new(memory_market, PLAYER).unwrap();
+        client.fetch_market().await.unwrap();
+
+        let metrics = Metrics::default();
+        let mut bookkeeper = Bookkeeper::new(&mut strategy, &metrics);
+        game::play_tick(
+            &mut client,
+            &mut bookkeeper,
+            1,
+            Duration::ZERO,
+            &Shutdown::never(),
+        )
+        .await
+        .unwrap();
+
+        // All of the cheaper supplier, the rest from the next one, booked per supplier
+        let by_supplier = bookkeeper.ledger.by_supplier();
+        assert_eq!(by_supplier.len(), 2);
+        assert_eq!(by_supplier[&SupplierId(2)].units, 2);
+        assert_eq!(by_supplier[&SupplierId(2)].spent, Money::from_f64(20.0));
+        assert_eq!(by_supplier[&SupplierId(1)].units, 2);
+        assert_eq!(by_supplier[&SupplierId(1)].spent, Money::from_f64(24.0));
+        assert_eq!(
+            bookkeeper
+                .ledger
+                .lots(ArticleId(1))
+                .map(|lot| (lot.supplier_id, lot.count))
+                .collect::<Vec<_>>(),
+            vec![(SupplierId(2), 2), (SupplierId(1), 2)]
+        );
+        assert_eq!(
+            client.market.player(PLAYER).unwrap().money,
+            Money::from_f64(956.0)
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use crate::client::types::*;
+use crate::client::{MarketSnapshot, MarketState};
+
+pub const PLAYER: PlayerId = PlayerId(1);
+pub const OTHER_PLAYER: PlayerId = PlayerId(2);
+
+// A small market for the tests. One supplier has ten units of every article, we are PLAYER.
+pub struct TestMarket {
+    pub player: Player,
+    pub articles: Vec<Article>,
+    pub tags: Vec<Tag>,
+    pub suppliers: Vec<Supplier>,
+    pub listings: Vec<Listing>,
+}
+
+impl Default for TestMarket {
+    fn default() -> Self {
+        Self {
+            player: Player {
+                id: PLAYER,
+                money: Money::from_f64(1000.0),
+                stock: Vec::new(),
+            },
+            articles: Vec::new(),
+            tags: Vec::new(),
+            suppliers: vec![Supplier {
+                id: SupplierId(1),
+                stock: Vec::new(),
+            }],
+            listings: Vec::new(),
+        }
+    }
+}
+
+impl TestMarket {
+    pub fn article(mut self, id: usize, tags: &[usize], supplier_price: f64) -> Self {
+        self.articles.push(Article {
+            id: ArticleId(id),
+            tags: tags.iter().copied().map(TagId).collect(),
+        });
+        self.suppliers[0].stock.push(Stock {
+            article_id: ArticleId(id),
+            stock: 10,
+            price: Money::from_f64(supplier_price),
+        });
+        self
+    }
+
+    pub fn tag(mut self, id: usize, similar_tags: &[usize]) -> Self {
+        self.tags.push(Tag {
+            id: TagId(id),
+            similar_tags: similar_tags.iter().copied().map(TagId).collect(),
+        });
+        self
+    }
+
+    // Sets what a supplier has of an article, the supplier is added if it's new
+    pub fn supplier(mut self, id: usize, article_id: usize, count: usize, price: f64) -> Self {
+        let stock = Stock {
+            article_id: ArticleId(article_id),
+            stock: count,
+            price: Money::from_f64(price),
+        };
+        match self
+            .suppliers
+            .iter_mut()
+            .find(|supplier| supplier.id == SupplierId(id))
+        {
+            Some(supplier) => {
+                supplier
+                    .stock
+                    .retain(|stock| stock.article_id != ArticleId(article_id));
+                supplier.stock.push(stock);
+            }
+            None => self.suppliers.push(Supplier {
+                id: SupplierId(id),
+                stock: vec![stock],
+            }),
+        }
+        self
+    }
+
+    pub fn stock(mut self, article_id: usize, count: usize) -> Self {
+        self.player.stock.push(PlayerStock {
+            article_id: ArticleId(article_id),
+            stock: count,
+        });
+        self
+    }
+
+    pub fn listing(
+        mut self,
+        id: usize,
+        player: PlayerId,
+        article_id: usize,
+        count: usize,
+        price: f64,
+    ) -> Self {
+        self.listings.push(Listing {
+            id: ListingId(id),
+            player,
+            article: ArticleId(article_id),
+            count,
+            price: Money::from_f64(price),
+        });
+        self
+    }
+
+    // Like a fetch of the whole market
+    pub fn fetch_into(&self, state: &mut MarketState) {
+        state.update_player(self.player.clone());
+        state.update_articles(self.articles.clone());
+        state.update_tags(self.tags.clone());
+        state.update_suppliers(self.suppliers.clone());
+        state.update_listings(self.listings.clone());
+    }
+
+    pub fn snapshot(&self) -> MarketSnapshot {
+        let mut state = MarketState::default();
+        self.fetch_into(&mut state);
+        MarketSnapshot::capture(&state, 1, Duration::ZERO)
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::client::types::{ArticleId, Money, TagId};
+use crate::client::MarketSnapshot;
+
+// How sales spread and fade in TagTrends
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrendSettings {
+    // Score a unit sold at the average supplier price adds to the tags of its article. Units
+    // sold at a higher price add more.
+    pub sale_weight: f64,
+    // Scores halve every this long
+    pub half_life: Duration,
+    // How many steps through similar tags a sale reaches
+    pub hops: usize,
+    // Share of the score passed on with every step
+    pub damping: f64,
+}
+
+// How much the tags are in demand. Sales raise the scores of their article's tags and, damped,
+// of tags similar to those. Scores fade over game time, so a tag stops trending once its
+// articles stop selling.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagTrends {
+    scores: HashMap<TagId, f64>,
+    // Game time the scores are decayed to
+    updated: Duration,
+}
+
+impl TagTrends {
+    pub fn score(&self, tag_id: TagId) -> f64 {
+        self.scores.get(&tag_id).copied().unwrap_or(0.0)
+    }
+
+    // Every tag that has a score, ordered by tag id
+    pub fn scores(&self) -> BTreeMap<TagId, f64> {
+        self.scores
+            .iter()
+            .map(|(tag_id, score)| (*tag_id, *score))
+            .collect()
+    }
+
+    // Highest scores first
+    pub fn top(&self, count: usize) -> Vec<(TagId, f64)> {
+        let mut scores = self.scores().into_iter().collect::<Vec<_>>();
+        scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        scores.truncate(count);
+        scores
+    }
+
+    // Let the scores fade up to the given game time. Time never runs backwards, e.g. after a
+    // restart with an older state the scores stay as they are.
+    pub fn decay_to(&mut self, elapsed: Duration, settings: &TrendSettings) {
+        let passed = match elapsed.checked_sub(self.updated) {
+            Some(passed) => passed,
+            None => return,
+        };
+        self.updated = elapsed;
+
+        let factor = 0.5f64.powf(passed.as_secs_f64() / settings.half_life.as_secs_f64());
+        for score in self.scores.values_mut() {
+            *score *= factor;
+        }
+        // Don't drag along tags nobody buys anymore
+        self.scores.retain(|_, score| *score > f64::EPSILON);
+    }
+
+    pub fn record_sale(
+        &mut self,
+        snapshot: &MarketSnapshot,
+        article_id: ArticleId,
+        count: usize,
+        unit_price: Money,
+        settings: &TrendSettings,
+    ) {
+        let article = match snapshot.article(article_id) {
+            Some(article) => article,
+            None => return,
+        };
+        let price_weight = match snapshot.article_price_history.get(&article_id) {
+            Some(history) if history.average_price().is_positive() => {
+                (unit_price.as_f64() / history.average_price().as_f64()).max(0.0)
+            }
+            _ => 1.0,
+        };
+        let signal = settings.sale_weight * count as f64 * price_weight;
+
+        for (tag_id, share) in spread(snapshot, &article.tags, settings) {
+            *self.scores.entry(tag_id).or_default() += signal * share;
+        }
+    }
+}
+
+// The share of a sale every tag gets: all of it for the article's own tags, damped once per
+// step through similar tags. A tag reached in several ways gets the biggest share only.
+fn spread(
+    snapshot: &MarketSnapshot,
+    article_tags: &[TagId],
+    settings: &TrendSettings,
+) -> HashMap<TagId, f64> {
+    let mut shares = article_tags
+        .iter()
+        .map(|tag_id| (*tag_id, 1.0))
+        .collect::<HashMap<_, _>>();
+    let mut queue = article_tags
+        .iter()
+        .map(|tag_id| (*tag_id, 0))
+        .collect::<VecDeque<_>>();
+
+    // Breadth first, so every tag is reached on its shortest way first
+    while let Some((tag_id, hop)) = queue.pop_front() {
+        if hop == settings.hops {
+            continue;
+        }
+        let similar_tags = match snapshot.tag(tag_id) {
+            Some(tag) => &tag.similar_tags,
+            None => continue,
+        };
+        let share = settings.damping.powi(hop as i32 + 1);
+        for similar_tag in similar_tags {
+            if shares.contains_key(similar_tag) {
+                continue;
+            }
+            shares.insert(*similar_tag, share);
+            queue.push_back((*similar_tag, hop + 1));
+        }
+    }
+
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::TestMarket;
+
+    const SETTINGS: TrendSettings = TrendSettings {
+        sale_weight: 1.0,
+        half_life: Duration::from_secs(60),
+        hops: 1,
+        damping: 0.5,
+    };
+
+    // Tag 1 is similar to 2, which is similar to 3
+    fn snapshot() -> MarketSnapshot {
+        TestMarket::default()
+            .tag(1, &[2])
+            .tag(2, &[1, 3])
+            .tag(3, &[2])
+            .article(1, &[1], 10.0)
+            .snapshot()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn scores_sales_of_the_article_tags() {
+        let mut trends = TagTrends::default();
+        // At the average price a unit weighs exactly the sale weight
+        trends.record_sale(
+            &snapshot(),
+            ArticleId(1),
+            2,
+            Money::from_f64(10.0),
+            &SETTINGS,
+        );
+        assert_close(trends.score(TagId(1)), 2.0);
+
+        trends.record_sale(
+            &snapshot(),
+            ArticleId(1),
+            1,
+            Money::from_f64(20.0),
+            &SETTINGS,
+        );
+        assert_close(trends.score(TagId(1)), 4.0);
+    }
+
+    #[test]
+    fn passes_a_damped_share_to_similar_tags() {
+        let mut trends = TagTrends::default();
+        trends.record_sale(
+            &snapshot(),
+            ArticleId(1),
+            2,
+            Money::from_f64(10.0),
+            &SETTINGS,
+        );
+
+        assert_close(trends.score(TagId(2)), SETTINGS.damping * 2.0);
+        // Two steps away is out of reach with one hop
+        assert_eq!(trends.score(TagId(3)), 0.0);
+        assert_eq!(trends.scores().len(), 2);
+
+        let mut trends = TagTrends::default();
+        let settings = TrendSettings {
+            hops: 2,
+            ..SETTINGS
+        };
+        trends.record_sale(
+            &snapshot(),
+            ArticleId(1),
+            2,
+            Money::from_f64(10.0),
+            &settings,
+        );
+        assert_close(trends.score(TagId(3)), 0.25 * 2.0);
+        // Tag 1 is also two steps from itself, but keeps its full share
+        assert_close(trends.score(TagId(1)), 2.0);
+    }
+
+    #[test]
+    fn halves_the_scores_every_half_life() {
+        let mut trends = TagTrends::default();
+        trends.record_sale(
+            &snapshot(),
+            ArticleId(1),
+            2,
+            Money::from_f64(10.0),
+            &SETTINGS,
+        );
+
+        trends.decay_to(Duration::from_secs(60), &SETTINGS);
+        assert_close(trends.score(TagId(1)), 1.0);
+        assert_close(trends.score(TagId(2)), 0.5);
+
+        trends.decay_to(Duration::from_secs(90), &SETTINGS);
+        assert_close(trends.score(TagId(1)), 0.5f64.sqrt());
+
+        // Time running backwards changes nothing
+        trends.decay_to(Duration::from_secs(30), &SETTINGS);
+        assert_close(trends.score(TagId(1)), 0.5f64.sqrt());
+    }
+
+    #[test]
+    fn forgets_tags_nobody_buys_anymore() {
+        let mut trends = TagTrends::default();
+        trends.record_sale(
+            &snapshot(),
+            ArticleId(1),
+            1,
+            Money::from_f64(10.0),
+            &SETTINGS,
+        );
+
+        trends.decay_to(Duration::from_secs(60 * 60), &SETTINGS);
+        assert!(trends.scores().is_empty());
+        assert!(trends.top(3).is_empty());
+    }
+}
@@ -0,0 +1,6 @@
+pub mod backtest;
+pub mod client;
+pub mod game;
+pub mod logging;
+pub mod metrics;
+pub mod simulator;
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+
+// How log lines look, picked at startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // One readable line per event, with the spans it happened in
+    #[default]
+    Human,
+    // One JSON object per line, for log collectors
+    Json,
+}
+
+// Install the global subscriber. RUST_LOG wins over the configured filter, so a single run
+// can be made chattier without touching the config.
+pub fn init(format: LogFormat, filter: &str) {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(filter))
+        .unwrap_or_else(|e| {
+            eprintln!("Invalid log filter {} ({}), using info", filter, e);
+            EnvFilter::new("info")
+        });
+
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match format {
+        LogFormat::Human => subscriber.try_init(),
+        LogFormat::Json => subscriber
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    };
+    if let Err(e) = result {
+        eprintln!("Couldn't set up logging ({})", e);
+    }
+}
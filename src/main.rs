@@ -1,8 +1,16 @@
-mod client;
-mod game;
-
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::Arc;
+
+use marketplacesimulation::metrics::Metrics;
+use marketplacesimulation::{backtest, client, game, logging, simulator};
+
+static SIMULATOR_ARTICLE_COUNT: usize = 50;
+static SIMULATOR_TAG_COUNT: usize = 15;
+static SIMULATOR_SUPPLIER_COUNT: usize = 3;
+
+static DEFAULT_CONFIG_PATH: &str = "marketplacesimulation.toml";
 
 #[tokio::main]
 async fn main() {
@@ -13,21 +21,147 @@ async fn main() {
     //          since they leak into the process list and whatnot. Instead, you should use environment variables.
     //          We only use it here because they are not critical and to simplify usage.
     let user_id = args.next();
+
+    if user_id.as_deref() == Some("--simulator") {
+        let addr = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+        run_simulator(&addr).await;
+        return;
+    }
+
+    if user_id.as_deref() == Some("--backtest") {
+        match args.next() {
+            Some(snapshot_file) => run_backtest(Path::new(&snapshot_file)).await,
+            None => {
+                eprintln!("{} --backtest <snapshot-file>", binary);
+                eprintln!("{} --replay <tape-file>", binary);
+                exit(2);
+            }
+        }
+        return;
+    }
+
+    if user_id.as_deref() == Some("--replay") {
+        match args.next() {
+            Some(tape_file) => run_replay(Path::new(&tape_file)).await,
+            None => {
+                eprintln!("{} --replay <tape-file>", binary);
+                exit(2);
+            }
+        }
+        return;
+    }
+
     let api_key = args.next();
 
     let opt_api_url = args.next();
 
     if user_id.is_none() || api_key.is_none() {
         eprintln!("{} <user-id> <api-key> [<api-url>]", binary);
+        eprintln!("{} --simulator [<bind-address>]", binary);
+        eprintln!("{} --backtest <snapshot-file>", binary);
+        eprintln!("{} --replay <tape-file>", binary);
         exit(2);
     }
 
     let user_id = user_id.unwrap();
     let api_key = api_key.unwrap();
 
+    let config = load_config();
+    println!("Effective configuration:");
+    println!("{}", config);
+    println!();
+
     println!("Initializing…");
 
-    let mut client = match client::Client::new(opt_api_url, user_id, api_key).await {
+    let metrics = serve_metrics(&config.metrics).await;
+
+    let tape_file = Some(PathBuf::from(&config.client.tape_file))
+        .filter(|tape_file| !tape_file.as_os_str().is_empty());
+    let mut client = match client::Client::recording(
+        opt_api_url,
+        user_id,
+        api_key,
+        tape_file.as_deref(),
+        config.client.retry.retry_policy(),
+        config.client.rate_limit.rate_limiter(),
+    )
+    .await
+    {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    let mut strategy = game::strategy(&config);
+    if let Err(e) = game::play(
+        &mut client,
+        strategy.as_mut(),
+        &config.engine,
+        &metrics,
+        game::Shutdown::on_signals(),
+    )
+    .await
+    {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+// Metrics are always collected, but only served if there is an address for it
+async fn serve_metrics(config: &game::MetricsConfig) -> Arc<Metrics> {
+    let metrics = Arc::new(Metrics::default());
+    if config.listen.is_empty() {
+        return metrics;
+    }
+
+    // Checked when the config was loaded
+    let addr = config.listen.parse().unwrap();
+    match metrics.clone().serve(addr).await {
+        Ok(addr) => println!("Metrics on http://{}/metrics", addr),
+        Err(e) => {
+            eprintln!("Couldn't serve metrics on {} ({})", addr, e);
+            exit(1);
+        }
+    }
+    metrics
+}
+
+// MARKETPLACE_CONFIG points to the config file, otherwise we look for marketplacesimulation.toml
+// in the working directory and fall back to the defaults. Logging is set up from it as well.
+fn load_config() -> game::Config {
+    let path = match env::var_os("MARKETPLACE_CONFIG") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|path| path.exists()),
+    };
+
+    match game::Config::load(path.as_deref()) {
+        Ok(config) => {
+            logging::init(config.log.format, &config.log.filter);
+            config
+        }
+        Err(e) => {
+            eprintln!(
+                "{}: {}",
+                path.as_deref()
+                    .map_or("<defaults>".into(), Path::to_string_lossy),
+                e
+            );
+            exit(2);
+        }
+    }
+}
+
+async fn run_replay(tape_file: &Path) {
+    let mut config = load_config();
+    // A replay must not touch the files of a real session
+    config.engine.state_file.clear();
+    config.engine.snapshot_file.clear();
+
+    println!("Replaying {}…", tape_file.display());
+
+    let mut client = match client::Client::replay(tape_file).await {
         Ok(client) => client,
         Err(e) => {
             eprintln!("{}", e);
@@ -35,5 +169,81 @@ async fn main() {
         }
     };
 
-    game::play(&mut client).await;
+    let mut strategy = game::strategy(&config);
+    if let Err(e) = game::play(
+        &mut client,
+        strategy.as_mut(),
+        &config.engine,
+        &Metrics::default(),
+        game::Shutdown::on_signals(),
+    )
+    .await
+    {
+        // Running out of tape is how every replay ends
+        eprintln!("{}", e);
+    }
+}
+
+async fn run_backtest(snapshot_file: &Path) {
+    let config = load_config();
+
+    let snapshots = match client::MarketSnapshot::load_all(snapshot_file) {
+        Ok(snapshots) => snapshots,
+        Err(e) => {
+            eprintln!("{}: {}", snapshot_file.display(), e);
+            exit(1);
+        }
+    };
+    println!("Backtesting {} recorded ticks…", snapshots.len());
+
+    let mut backtest =
+        backtest::Backtest::new(snapshots).customers(config.backtest.customer_model());
+    if config.backtest.seed != 0 {
+        backtest = backtest.seed(config.backtest.seed);
+    }
+    if config.backtest.starting_money.is_positive() {
+        backtest = backtest.starting_money(config.backtest.starting_money);
+    }
+
+    let mut strategy = game::strategy(&config);
+    match backtest.run(strategy.as_mut()).await {
+        Ok(report) => {
+            println!();
+            print!("{}", report);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    }
+}
+
+async fn run_simulator(addr: &str) {
+    logging::init(logging::LogFormat::default(), "info");
+
+    let addr = match addr.parse() {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("Invalid bind address {}: {}", addr, e);
+            exit(2);
+        }
+    };
+
+    let handle = match simulator::Simulator::with_random_market(
+        SIMULATOR_ARTICLE_COUNT,
+        SIMULATOR_TAG_COUNT,
+        SIMULATOR_SUPPLIER_COUNT,
+    )
+    .spawn(addr)
+    .await
+    {
+        Ok(handle) => handle,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    println!("Simulator listening on {}", handle.url);
+    handle.wait().await;
 }
@@ -0,0 +1,500 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tracing::warn;
+
+use crate::client::types::{ArticleId, Money, SupplierId, TagId};
+use crate::client::{Portfolio, RateLimitStats, RequestStats};
+
+static TEXT_FORMAT: &str = "text/plain; version=0.0.4";
+
+// What the running bot looks like from the outside. The game loop keeps it up to date, the
+// metrics server renders it in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    values: Mutex<Values>,
+}
+
+#[derive(Default)]
+struct Values {
+    money: Money,
+    piggybank: Option<Money>,
+    portfolio: Portfolio,
+    realized: Money,
+    unrealized: Money,
+    units_sold: BTreeMap<ArticleId, u64>,
+    units_bought: BTreeMap<SupplierId, u64>,
+    tag_trends: BTreeMap<TagId, f64>,
+    requests: BTreeMap<String, RequestStats>,
+    rate_limits: BTreeMap<String, RateLimitStats>,
+    ticks: u64,
+    tick_duration: Duration,
+    overruns: u64,
+    missed_ticks: u64,
+}
+
+impl Metrics {
+    pub fn set_player(&self, money: Money, piggybank: Option<Money>) {
+        let mut values = self.values.lock().unwrap();
+        values.money = money;
+        values.piggybank = piggybank;
+    }
+
+    pub fn set_portfolio(&self, portfolio: Portfolio) {
+        self.values.lock().unwrap().portfolio = portfolio;
+    }
+
+    pub fn set_pnl(&self, realized: Money, unrealized: Money) {
+        let mut values = self.values.lock().unwrap();
+        values.realized = realized;
+        values.unrealized = unrealized;
+    }
+
+    pub fn add_units_sold(&self, article_id: ArticleId, count: usize) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .units_sold
+            .entry(article_id)
+            .or_default() += count as u64;
+    }
+
+    pub fn add_units_bought(&self, supplier_id: SupplierId, count: usize) {
+        *self
+            .values
+            .lock()
+            .unwrap()
+            .units_bought
+            .entry(supplier_id)
+            .or_default() += count as u64;
+    }
+
+    pub fn set_tag_trends(&self, tag_trends: BTreeMap<TagId, f64>) {
+        self.values.lock().unwrap().tag_trends = tag_trends;
+    }
+
+    // The client keeps the totals, so they are replaced as a whole
+    pub fn set_request_stats(&self, stats: HashMap<String, RequestStats>) {
+        self.values.lock().unwrap().requests = stats.into_iter().collect();
+    }
+
+    pub fn set_rate_limit_stats(&self, stats: HashMap<String, RateLimitStats>) {
+        self.values.lock().unwrap().rate_limits = stats.into_iter().collect();
+    }
+
+    pub fn record_tick(&self, duration: Duration, overruns: u64, missed_ticks: u64) {
+        let mut values = self.values.lock().unwrap();
+        values.ticks += 1;
+        values.tick_duration = duration;
+        values.overruns = overruns;
+        values.missed_ticks = missed_ticks;
+    }
+
+    pub fn render(&self) -> String {
+        let values = self.values.lock().unwrap();
+        let mut out = String::new();
+
+        gauge(
+            &mut out,
+            "marketplace_player_money",
+            "Money of our player",
+            values.money.as_f64(),
+        );
+        if let Some(piggybank) = values.piggybank {
+            gauge(
+                &mut out,
+                "marketplace_piggybank",
+                "Money the strategy keeps aside",
+                piggybank.as_f64(),
+            );
+        }
+        gauge(
+            &mut out,
+            "marketplace_portfolio_items",
+            "Units in our listings",
+            values.portfolio.items as f64,
+        );
+        gauge(
+            &mut out,
+            "marketplace_portfolio_min_value",
+            "Value of our listings priced down to the average supplier price",
+            values.portfolio.min_value.as_f64(),
+        );
+        gauge(
+            &mut out,
+            "marketplace_portfolio_max_value",
+            "Value of our listings at their current prices",
+            values.portfolio.max_value.as_f64(),
+        );
+        gauge(
+            &mut out,
+            "marketplace_realized_pnl",
+            "Revenue of our sales minus what the sold units cost us",
+            values.realized.as_f64(),
+        );
+        gauge(
+            &mut out,
+            "marketplace_unrealized_pnl",
+            "Units we still hold at the average supplier price minus what they cost us",
+            values.unrealized.as_f64(),
+        );
+
+        header(
+            &mut out,
+            "marketplace_units_sold_total",
+            "counter",
+            "Units customers bought from our listings",
+        );
+        for (article_id, count) in &values.units_sold {
+            let article_id = article_id.to_string();
+            sample(
+                &mut out,
+                "marketplace_units_sold_total",
+                &[("article_id", &article_id)],
+                *count as f64,
+            );
+        }
+        header(
+            &mut out,
+            "marketplace_units_bought_total",
+            "counter",
+            "Units we bought from suppliers",
+        );
+        for (supplier_id, count) in &values.units_bought {
+            let supplier_id = supplier_id.to_string();
+            sample(
+                &mut out,
+                "marketplace_units_bought_total",
+                &[("supplier_id", &supplier_id)],
+                *count as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "marketplace_tag_trend_score",
+            "gauge",
+            "How much the strategy thinks a tag is in demand",
+        );
+        for (tag_id, score) in &values.tag_trends {
+            let tag_id = tag_id.to_string();
+            sample(
+                &mut out,
+                "marketplace_tag_trend_score",
+                &[("tag_id", &tag_id)],
+                *score,
+            );
+        }
+
+        header(
+            &mut out,
+            "marketplace_api_requests_total",
+            "counter",
+            "Requests sent to the server, retries included",
+        );
+        for (endpoint, stats) in &values.requests {
+            let labels = [("endpoint", endpoint.as_str())];
+            sample(
+                &mut out,
+                "marketplace_api_requests_total",
+                &labels,
+                stats.requests as f64,
+            );
+        }
+        header(
+            &mut out,
+            "marketplace_api_errors_total",
+            "counter",
+            "Requests that failed or didn't get a 200 OK",
+        );
+        for (endpoint, stats) in &values.requests {
+            let labels = [("endpoint", endpoint.as_str())];
+            sample(
+                &mut out,
+                "marketplace_api_errors_total",
+                &labels,
+                stats.errors as f64,
+            );
+        }
+        header(
+            &mut out,
+            "marketplace_api_request_duration_seconds",
+            "summary",
+            "Time until the server answered a request",
+        );
+        for (endpoint, stats) in &values.requests {
+            let labels = [("endpoint", endpoint.as_str())];
+            sample(
+                &mut out,
+                "marketplace_api_request_duration_seconds_sum",
+                &labels,
+                stats.total_latency.as_secs_f64(),
+            );
+            sample(
+                &mut out,
+                "marketplace_api_request_duration_seconds_count",
+                &labels,
+                stats.requests as f64,
+            );
+        }
+
+        header(
+            &mut out,
+            "marketplace_rate_limit_waits_total",
+            "counter",
+            "Requests that had to wait for the client-side rate limiter",
+        );
+        for (endpoint, stats) in &values.rate_limits {
+            let labels = [("endpoint", endpoint.as_str())];
+            sample(
+                &mut out,
+                "marketplace_rate_limit_waits_total",
+                &labels,
+                stats.waits as f64,
+            );
+        }
+        header(
+            &mut out,
+            "marketplace_rate_limit_wait_seconds_total",
+            "counter",
+            "Time requests spent waiting for the client-side rate limiter",
+        );
+        for (endpoint, stats) in &values.rate_limits {
+            let labels = [("endpoint", endpoint.as_str())];
+            sample(
+                &mut out,
+                "marketplace_rate_limit_wait_seconds_total",
+                &labels,
+                stats.total_wait.as_secs_f64(),
+            );
+        }
+
+        counter(
+            &mut out,
+            "marketplace_ticks_total",
+            "Ticks played since the start",
+            values.ticks,
+        );
+        gauge(
+            &mut out,
+            "marketplace_tick_duration_seconds",
+            "How long the last tick took",
+            values.tick_duration.as_secs_f64(),
+        );
+        counter(
+            &mut out,
+            "marketplace_tick_overruns_total",
+            "Ticks that took longer than the tick timer",
+            values.overruns,
+        );
+        counter(
+            &mut out,
+            "marketplace_missed_ticks_total",
+            "Ticks that didn't happen on time because a tick ran long",
+            values.missed_ticks,
+        );
+
+        out
+    }
+
+    // Serve /metrics on the address until the process ends
+    pub async fn serve(self: Arc<Self>, addr: SocketAddr) -> Result<SocketAddr, hyper::Error> {
+        let server = Server::try_bind(&addr)?.serve(make_service_fn(move |_| {
+            let metrics = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let metrics = metrics.clone();
+                    async move { Ok::<_, Infallible>(handle(&metrics, req)) }
+                }))
+            }
+        }));
+        let addr = server.local_addr();
+
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                warn!(error = %e, "Metrics server stopped");
+            }
+        });
+
+        Ok(addr)
+    }
+}
+
+fn handle(metrics: &Metrics, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    Response::builder()
+        .header(CONTENT_TYPE, TEXT_FORMAT)
+        .body(Body::from(metrics.render()))
+        .unwrap()
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels = labels
+            .iter()
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+            .collect::<Vec<_>>();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {}", value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    header(out, name, "gauge", help);
+    sample(out, name, &[], value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    sample(out, name, &[], value as f64);
+}
+
+fn escape(label_value: &str) -> String {
+    label_value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(metrics: &Metrics) -> Vec<String> {
+        metrics.render().lines().map(str::to_string).collect()
+    }
+
+    fn has(lines: &[String], line: &str) -> bool {
+        lines.iter().any(|l| l == line)
+    }
+
+    #[test]
+    fn renders_gauges_and_counters() {
+        let metrics = Metrics::default();
+        metrics.set_player(Money::from_f64(1234.5), None);
+        metrics.add_units_sold(ArticleId(7), 2);
+        metrics.add_units_sold(ArticleId(7), 3);
+        metrics.record_tick(Duration::from_millis(250), 1, 4);
+
+        let lines = lines(&metrics);
+        assert!(has(
+            &lines,
+            "# HELP marketplace_player_money Money of our player"
+        ));
+        assert!(has(&lines, "# TYPE marketplace_player_money gauge"));
+        assert!(has(&lines, "marketplace_player_money 1234.5"));
+        assert!(has(&lines, "# TYPE marketplace_units_sold_total counter"));
+        assert!(has(
+            &lines,
+            "marketplace_units_sold_total{article_id=\"7\"} 5"
+        ));
+        assert!(has(&lines, "marketplace_ticks_total 1"));
+        assert!(has(&lines, "marketplace_tick_duration_seconds 0.25"));
+        assert!(has(&lines, "marketplace_missed_ticks_total 4"));
+        // No piggybank, no line for it
+        assert!(!lines.iter().any(|line| line.contains("piggybank")));
+
+        // Every sample follows the HELP and TYPE lines of its metric
+        for line in lines.iter().filter(|line| !line.starts_with('#')) {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let metric = name
+                .strip_suffix("_sum")
+                .or_else(|| name.strip_suffix("_count"))
+                .unwrap_or(name);
+            assert!(lines
+                .iter()
+                .any(|l| l.starts_with(&format!("# HELP {} ", metric))));
+            assert!(lines
+                .iter()
+                .any(|l| l.starts_with(&format!("# TYPE {} ", metric))));
+        }
+    }
+
+    #[test]
+    fn renders_request_summaries_with_escaped_labels() {
+        let metrics = Metrics::default();
+        metrics.set_request_stats(HashMap::from([(
+            "/odd\"path\\".to_string(),
+            RequestStats {
+                requests: 4,
+                errors: 1,
+                total_latency: Duration::from_millis(500),
+            },
+        )]));
+
+        let lines = lines(&metrics);
+        let labels = "{endpoint=\"/odd\\\"path\\\\\"}";
+        assert!(has(
+            &lines,
+            "# TYPE marketplace_api_request_duration_seconds summary"
+        ));
+        assert!(has(
+            &lines,
+            &format!("marketplace_api_requests_total{} 4", labels)
+        ));
+        assert!(has(
+            &lines,
+            &format!("marketplace_api_errors_total{} 1", labels)
+        ));
+        assert!(has(
+            &lines,
+            &format!("marketplace_api_request_duration_seconds_sum{} 0.5", labels)
+        ));
+        assert!(has(
+            &lines,
+            &format!("marketplace_api_request_duration_seconds_count{} 4", labels)
+        ));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[tokio::test]
+    async fn serves_only_get_metrics() {
+        let metrics = Metrics::default();
+        let request = |method: Method, path: &str| {
+            Request::builder()
+                .method(method)
+                .uri(path)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = handle(&metrics, request(Method::GET, "/metrics"));
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], TEXT_FORMAT);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, metrics.render());
+
+        for (method, path) in [
+            (Method::GET, "/"),
+            (Method::GET, "/metrics/extra"),
+            (Method::POST, "/metrics"),
+        ] {
+            let response = handle(&metrics, request(method, path));
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+
+use crate::client::memory::MemoryMarket;
+use crate::client::types::{ArticleId, ListingId, Money, PlayerId};
+
+// Simulated customers. Each one wants a random article and looks at the cheapest listing for it.
+// The further that listing is above the supplier price, the less likely the customer buys.
+#[derive(Debug, Clone)]
+pub struct CustomerModel {
+    pub customers_per_tick: usize,
+    pub max_units_per_customer: usize,
+    // Nobody buys at or above this multiple of the supplier price
+    pub max_price_multiplier: f64,
+}
+
+impl Default for CustomerModel {
+    fn default() -> Self {
+        Self {
+            customers_per_tick: 20,
+            max_units_per_customer: 3,
+            // Matches what we saw on the hackathon server, a 10x increase was still fine
+            max_price_multiplier: 11.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sale {
+    pub listing_id: ListingId,
+    pub article_id: ArticleId,
+    pub seller: PlayerId,
+    pub count: usize,
+    pub price_per_unit: Money,
+}
+
+impl CustomerModel {
+    // Probability that a customer accepts a price, relative to the supplier price
+    pub fn buy_probability(&self, price: Money, supplier_price: Money) -> f64 {
+        if !price.is_positive() || !supplier_price.is_positive() {
+            // Bogus listings, nobody trusts those
+            return 0.0;
+        }
+
+        let multiplier = price.as_f64() / supplier_price.as_f64();
+        if multiplier <= 1.0 {
+            return 1.0;
+        }
+
+        (1.0 - (multiplier - 1.0) / (self.max_price_multiplier - 1.0)).clamp(0.0, 1.0)
+    }
+
+    pub fn serve<R: Rng>(&self, market: &mut MemoryMarket, rng: &mut R) -> Vec<Sale> {
+        let mut sales = Vec::new();
+
+        if market.articles.is_empty() {
+            return sales;
+        }
+
+        let supplier_prices = lowest_supplier_prices(market);
+
+        for _ in 0..self.customers_per_tick {
+            let article_id = market.articles[rng.gen_range(0..market.articles.len())].id;
+
+            let supplier_price = match supplier_prices.get(&article_id) {
+                Some(price) => *price,
+                None => continue, // Nobody knows what this article is worth
+            };
+
+            let cheapest_listing = market
+                .listings
+                .iter()
+                .filter(|listing| {
+                    listing.article == article_id
+                        && listing.count > 0
+                        && listing.price.is_positive()
+                })
+                .min_by_key(|listing| listing.price);
+            let listing = match cheapest_listing {
+                Some(listing) => listing,
+                None => continue,
+            };
+
+            if !rng.gen_bool(self.buy_probability(listing.price, supplier_price)) {
+                continue;
+            }
+
+            let count = rng
+                .gen_range(1..=self.max_units_per_customer.max(1))
+                .min(listing.count);
+            let sale = Sale {
+                listing_id: listing.id,
+                article_id,
+                seller: listing.player,
+                count,
+                price_per_unit: listing.price,
+            };
+
+            if market.sell_from_listing(sale.listing_id, sale.count) {
+                sales.push(sale);
+            }
+        }
+
+        sales
+    }
+}
+
+pub fn lowest_supplier_prices(market: &MemoryMarket) -> HashMap<ArticleId, Money> {
+    let mut prices: HashMap<ArticleId, Money> = HashMap::new();
+    for stock in market
+        .suppliers
+        .iter()
+        .flat_map(|supplier| supplier.stock.iter())
+    {
+        prices
+            .entry(stock.article_id)
+            .and_modify(|price| *price = (*price).min(stock.price))
+            .or_insert(stock.price);
+    }
+    prices
+}
@@ -0,0 +1,424 @@
+pub mod customers;
+
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::client::memory::MemoryMarket;
+use crate::client::types::*;
+
+use self::customers::CustomerModel;
+
+static DEFAULT_STARTING_MONEY: Money = Money::from_micros(10_000_000_000); // 10000
+static DEFAULT_CUSTOMER_TICK_IN_MS: u64 = 1000;
+
+// Local stand-in for the hackathon server. It serves the same REST API as the real one,
+// so a client::Client pointed at its url can't tell the difference.
+pub struct Simulator {
+    world: Arc<Mutex<World>>,
+    customer_tick: Duration,
+}
+
+pub struct World {
+    pub market: MemoryMarket,
+    pub customers: CustomerModel,
+    pub starting_money: Money,
+    // Paths that answer 500 Internal Server Error, to see how clients cope with an outage
+    pub outages: HashSet<String>,
+
+    // user id -> (api key, player id)
+    credentials: HashMap<String, (String, PlayerId)>,
+    initial_supplier_stock: HashMap<(SupplierId, ArticleId), usize>,
+    rng: StdRng,
+}
+
+pub struct SimulatorHandle {
+    pub addr: SocketAddr,
+    pub url: String,
+
+    world: Arc<Mutex<World>>,
+    server_task: tokio::task::JoinHandle<()>,
+    customer_task: tokio::task::JoinHandle<()>,
+}
+
+impl Simulator {
+    pub fn new(market: MemoryMarket) -> Self {
+        let initial_supplier_stock = market
+            .suppliers
+            .iter()
+            .flat_map(|supplier| {
+                supplier
+                    .stock
+                    .iter()
+                    .map(move |stock| ((supplier.id, stock.article_id), stock.stock))
+            })
+            .collect();
+
+        Self {
+            world: Arc::new(Mutex::new(World {
+                market,
+                customers: CustomerModel::default(),
+                starting_money: DEFAULT_STARTING_MONEY,
+                outages: HashSet::new(),
+
+                credentials: HashMap::new(),
+                initial_supplier_stock,
+                rng: StdRng::from_entropy(),
+            })),
+            customer_tick: Duration::from_millis(DEFAULT_CUSTOMER_TICK_IN_MS),
+        }
+    }
+
+    // A random catalog, good enough to let a bot loose on it
+    pub fn with_random_market(
+        article_count: usize,
+        tag_count: usize,
+        supplier_count: usize,
+    ) -> Self {
+        Self::new(random_market(
+            &mut StdRng::from_entropy(),
+            article_count,
+            tag_count,
+            supplier_count,
+        ))
+    }
+
+    pub fn customers(self, customers: CustomerModel) -> Self {
+        self.world.lock().unwrap().customers = customers;
+        self
+    }
+
+    pub fn customer_tick(mut self, customer_tick: Duration) -> Self {
+        self.customer_tick = customer_tick;
+        self
+    }
+
+    pub fn starting_money(self, starting_money: Money) -> Self {
+        self.world.lock().unwrap().starting_money = starting_money;
+        self
+    }
+
+    pub fn seed(self, seed: u64) -> Self {
+        self.world.lock().unwrap().rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    // Players don't have to be registered, unknown users are created on their first request.
+    // Registering them upfront allows a custom amount of money though.
+    pub fn add_player(self, user_id: &str, api_key: &str, money: Money) -> Self {
+        {
+            let mut world = self.world.lock().unwrap();
+            let player_id = world.market.add_player(money);
+            world
+                .credentials
+                .insert(user_id.to_string(), (api_key.to_string(), player_id));
+        }
+        self
+    }
+
+    pub async fn spawn(self, addr: SocketAddr) -> Result<SimulatorHandle, hyper::Error> {
+        let world = self.world.clone();
+        let server = Server::try_bind(&addr)?.serve(make_service_fn(move |_| {
+            let world = world.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let world = world.clone();
+                    async move { Ok::<_, Infallible>(handle(&world, req).await) }
+                }))
+            }
+        }));
+        let addr = server.local_addr();
+
+        let server_task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                tracing::warn!(error = %e, "Simulator: Server stopped");
+            }
+        });
+
+        let world = self.world.clone();
+        let customer_tick = self.customer_tick;
+        let customer_task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(customer_tick);
+            loop {
+                interval.tick().await;
+                world.lock().unwrap().tick();
+            }
+        });
+
+        Ok(SimulatorHandle {
+            addr,
+            url: format!("http://{}", addr),
+
+            world: self.world,
+            server_task,
+            customer_task,
+        })
+    }
+}
+
+impl SimulatorHandle {
+    pub fn world(&self) -> std::sync::MutexGuard<'_, World> {
+        self.world.lock().unwrap()
+    }
+
+    pub async fn wait(self) {
+        let _ = self.server_task.await;
+        self.customer_task.abort();
+    }
+
+    pub fn shutdown(self) {
+        self.server_task.abort();
+        self.customer_task.abort();
+    }
+}
+
+impl World {
+    // One round of customers, afterwards the suppliers restock
+    pub fn tick(&mut self) -> Vec<customers::Sale> {
+        let sales = self.customers.serve(&mut self.market, &mut self.rng);
+
+        for supplier in self.market.suppliers.iter_mut() {
+            for stock in supplier.stock.iter_mut() {
+                if let Some(initial_stock) = self
+                    .initial_supplier_stock
+                    .get(&(supplier.id, stock.article_id))
+                {
+                    if stock.stock < *initial_stock {
+                        stock.stock += 1;
+                    }
+                }
+            }
+        }
+
+        sales
+    }
+
+    fn authorize(&mut self, req: &Request<Body>) -> Option<PlayerId> {
+        let header = req.headers().get(hyper::header::AUTHORIZATION)?;
+        let encoded = header.to_str().ok()?.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(base64::decode(encoded).ok()?).ok()?;
+        let (user_id, api_key) = decoded.split_once(':')?;
+
+        match self.credentials.get(user_id) {
+            Some((known_api_key, player_id)) => {
+                if known_api_key == api_key {
+                    Some(*player_id)
+                } else {
+                    None
+                }
+            }
+            None => {
+                let player_id = self.market.add_player(self.starting_money);
+                self.credentials
+                    .insert(user_id.to_string(), (api_key.to_string(), player_id));
+                Some(player_id)
+            }
+        }
+    }
+}
+
+async fn handle(world: &Mutex<World>, req: Request<Body>) -> Response<Body> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+
+    if world.lock().unwrap().outages.contains(&path) {
+        return status(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let player_id = world.lock().unwrap().authorize(&req);
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return status(StatusCode::BAD_REQUEST),
+    };
+
+    let mut world = world.lock().unwrap();
+    let market = &mut world.market;
+
+    match (&method, segments.as_slice()) {
+        (&Method::GET, ["article"]) => json(&market.articles),
+        (&Method::GET, ["tag"]) => json(&market.tags),
+        (&Method::GET, ["supplier"]) => json(&market.suppliers),
+        (&Method::GET, ["listing"]) => json(&market.listings),
+        (&Method::GET, ["player", "self"]) => match player_id.and_then(|id| market.player(id)) {
+            // The real API returns a list with exactly one player
+            Some(player) => json(&vec![player]),
+            None => status(StatusCode::UNAUTHORIZED),
+        },
+        (&Method::POST, ["supplier", supplier_id, "article", article_id, "buy"]) => {
+            let player_id = match player_id {
+                Some(player_id) => player_id,
+                None => return status(StatusCode::UNAUTHORIZED),
+            };
+            let (supplier_id, article_id) = match (supplier_id.parse(), article_id.parse()) {
+                (Ok(supplier_id), Ok(article_id)) => (supplier_id, article_id),
+                _ => return status(StatusCode::NOT_FOUND),
+            };
+            let body = match parse::<BuyFromSupplierBody>(&body) {
+                Some(body) => body,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+
+            if market.buy_from_supplier(
+                player_id,
+                supplier_id,
+                article_id,
+                body.count,
+                body.price_per_unit,
+            ) {
+                status(StatusCode::OK)
+            } else {
+                status(StatusCode::BAD_REQUEST)
+            }
+        }
+        (&Method::POST, ["listing", "new"]) => {
+            let player_id = match player_id {
+                Some(player_id) => player_id,
+                None => return status(StatusCode::UNAUTHORIZED),
+            };
+            let body = match parse::<CreateListingBody>(&body) {
+                Some(body) => body,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+
+            match market.create_listing(player_id, body.article, body.count, body.price) {
+                Some(id) => json(&HashMap::from([("id", id)])),
+                None => status(StatusCode::BAD_REQUEST),
+            }
+        }
+        (&Method::PUT, ["listing", listing_id]) => {
+            let player_id = match player_id {
+                Some(player_id) => player_id,
+                None => return status(StatusCode::UNAUTHORIZED),
+            };
+            let listing_id = match listing_id.parse() {
+                Ok(listing_id) => listing_id,
+                Err(_) => return status(StatusCode::NOT_FOUND),
+            };
+            let body = match parse::<UpdateListingBody>(&body) {
+                Some(body) => body,
+                None => return status(StatusCode::BAD_REQUEST),
+            };
+
+            if market.update_listing(player_id, listing_id, body.count, body.price) {
+                status(StatusCode::OK)
+            } else {
+                status(StatusCode::BAD_REQUEST)
+            }
+        }
+        (&Method::DELETE, ["listing", listing_id]) => {
+            let player_id = match player_id {
+                Some(player_id) => player_id,
+                None => return status(StatusCode::UNAUTHORIZED),
+            };
+            let listing_id = match listing_id.parse() {
+                Ok(listing_id) => listing_id,
+                Err(_) => return status(StatusCode::NOT_FOUND),
+            };
+
+            if market.delete_listing(player_id, listing_id) {
+                status(StatusCode::OK)
+            } else {
+                status(StatusCode::BAD_REQUEST)
+            }
+        }
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &[u8]) -> Option<T> {
+    serde_json::from_slice(body).ok()
+}
+
+fn json<T: Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap(),
+        Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn random_market<R: Rng>(
+    rng: &mut R,
+    article_count: usize,
+    tag_count: usize,
+    supplier_count: usize,
+) -> MemoryMarket {
+    let tag_count = tag_count.max(1);
+
+    let tags = (1..=tag_count)
+        .map(|id| Tag {
+            id: TagId(id),
+            similar_tags: (1..=tag_count)
+                .filter(|similar_tag| *similar_tag != id && rng.gen_bool(0.1))
+                .map(TagId)
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    let articles = (1..=article_count)
+        .map(|id| {
+            let mut article_tags = (0..rng.gen_range(1..=3))
+                .map(|_| TagId(rng.gen_range(1..=tag_count)))
+                .collect::<Vec<_>>();
+            article_tags.sort_unstable();
+            article_tags.dedup();
+            Article {
+                id: ArticleId(id),
+                tags: article_tags,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Like on the hackathon server, all suppliers have the same price for an article
+    let prices = articles
+        .iter()
+        .map(|article| {
+            // Whole cents, like real prices
+            let price = Money::from_micros(rng.gen_range(100..10_000) * 10_000);
+            (article.id, price)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let suppliers = (1..=supplier_count)
+        .map(|id| Supplier {
+            id: SupplierId(id),
+            stock: articles
+                .iter()
+                .filter_map(|article| {
+                    if !rng.gen_bool(0.7) {
+                        return None;
+                    }
+                    Some(Stock {
+                        article_id: article.id,
+                        stock: rng.gen_range(5..50),
+                        price: prices[&article.id],
+                    })
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    MemoryMarket::new(articles, tags, suppliers)
+}
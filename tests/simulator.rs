@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use marketplacesimulation::client::memory::MemoryMarket;
+use marketplacesimulation::client::types::*;
+use marketplacesimulation::client::{Client, MarketApi, MarketPart, RateLimiter, RetryPolicy};
+use marketplacesimulation::simulator::{Simulator, SimulatorHandle};
+
+const STARTING_MONEY: Money = Money::from_micros(1_000_000_000); // 1000
+const SUPPLIER_PRICE: Money = Money::from_micros(10_000_000); // 10
+
+// One article from one supplier, so every customer wants the same thing
+fn market() -> MemoryMarket {
+    MemoryMarket::new(
+        vec![Article {
+            id: ArticleId(1),
+            tags: vec![TagId(1)],
+        }],
+        vec![Tag {
+            id: TagId(1),
+            similar_tags: Vec::new(),
+        }],
+        vec![Supplier {
+            id: SupplierId(1),
+            stock: vec![Stock {
+                article_id: ArticleId(1),
+                stock: 10,
+                price: SUPPLIER_PRICE,
+            }],
+        }],
+    )
+}
+
+// Customers only come when the test sends them
+async fn start() -> (SimulatorHandle, Client) {
+    let handle = Simulator::new(market())
+        .customer_tick(Duration::from_secs(60 * 60))
+        .starting_money(STARTING_MONEY)
+        .seed(7)
+        .spawn("127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+
+    let mut client = Client::new(
+        Some(handle.url.clone()),
+        "tester".to_string(),
+        "secret".to_string(),
+    )
+    .await
+    .unwrap();
+    client.set_rate_limiter(RateLimiter::unlimited());
+
+    (handle, client)
+}
+
+#[tokio::test]
+async fn fetches_the_market() {
+    let (handle, mut client) = start().await;
+
+    let report = client.fetch_market().await.unwrap();
+    assert!(report.is_complete());
+
+    let state = client.state();
+    assert_eq!(state.articles, market().articles);
+    assert_eq!(state.tags, market().tags);
+    assert_eq!(state.suppliers, market().suppliers);
+    assert!(state.listings.is_empty());
+    assert_eq!(state.player.money, STARTING_MONEY);
+    assert!(state.player.stock.is_empty());
+
+    handle.shutdown();
+}
+
+#[tokio::test]
+async fn buys_from_suppliers() {
+    let (handle, mut client) = start().await;
+
+    client
+        .buy_from_supplier(SupplierId(1), ArticleId(1), 3, SUPPLIER_PRICE)
+        .await
+        .unwrap();
+    // Below the supplier price nobody sells
+    assert!(client
+        .buy_from_supplier(SupplierId(1), ArticleId(1), 1, SUPPLIER_PRICE / 2)
+        .await
+        .is_err());
+
+    client.fetch_market().await.unwrap();
+    let state = client.state();
+    assert_eq!(state.player.money, STARTING_MONEY - SUPPLIER_PRICE * 3);
+    assert_eq!(
+        state.player.stock,
+        vec![PlayerStock {
+            article_id: ArticleId(1),
+            stock: 3,
+        }]
+    );
+    assert_eq!(state.suppliers[0].stock[0].stock, 7);
+
+    handle.shutdown();
+}
+
+#[tokio::test]
+async fn creates_and_updates_listings() {
+    let (handle, mut client) = start().await;
+    client
+        .buy_from_supplier(SupplierId(1), ArticleId(1), 3, SUPPLIER_PRICE)
+        .await
+        .unwrap();
+
+    let listing_id = client
+        .create_listing(ArticleId(1), 2, Money::from_f64(25.0))
+        .await
+        .unwrap();
+    client.fetch_market().await.unwrap();
+    let player_id = client.state().player.id;
+    assert_eq!(
+        client.state().listings,
+        vec![Listing {
+            id: listing_id,
+            player: player_id,
+            article: ArticleId(1),
+            count: 2,
+            price: Money::from_f64(25.0),
+        }]
+    );
+    assert_eq!(client.state().player.stock[0].stock, 1);
+
+    // The rest of the stock goes into the listing
+    client
+        .update_listing(listing_id, 3, Money::from_f64(20.0))
+        .await
+        .unwrap();
+    client.fetch_market().await.unwrap();
+    let listing = &client.state().listings[0];
+    assert_eq!(listing.count, 3);
+    assert_eq!(listing.price, Money::from_f64(20.0));
+    assert!(client.state().player.stock.is_empty());
+
+    handle.shutdown();
+}
+
+#[tokio::test]
+async fn customers_buy_cheap_listings() {
+    let (handle, mut client) = start().await;
+    client
+        .buy_from_supplier(SupplierId(1), ArticleId(1), 3, SUPPLIER_PRICE)
+        .await
+        .unwrap();
+    // At the supplier price every customer buys
+    let listing_id = client
+        .create_listing(ArticleId(1), 3, SUPPLIER_PRICE)
+        .await
+        .unwrap();
+
+    let sales = handle.world().tick();
+    assert!(!sales.is_empty());
+    assert!(sales.iter().all(|sale| sale.listing_id == listing_id));
+    assert_eq!(sales.iter().map(|sale| sale.count).sum::<usize>(), 3);
+
+    client.fetch_market().await.unwrap();
+    assert_eq!(client.state().listings[0].count, 0);
+    assert_eq!(client.state().player.money, STARTING_MONEY);
+
+    handle.shutdown();
+}
+
+#[tokio::test]
+async fn fetches_what_it_can_during_an_outage() {
+    let (handle, mut client) = start().await;
+    // Fail right away instead of retrying the outage
+    client.set_retry_policy(RetryPolicy::none());
+    client.fetch_market().await.unwrap();
+
+    {
+        let mut world = handle.world();
+        world.market.articles.push(Article {
+            id: ArticleId(2),
+            tags: vec![TagId(2)],
+        });
+        world.market.tags.push(Tag {
+            id: TagId(2),
+            similar_tags: Vec::new(),
+        });
+        world
+            .outages
+            .insert(MarketPart::Tags.endpoint().to_string());
+    }
+
+    // The tags stay as they were, everything else is new
+    let report = client.fetch_market().await.unwrap();
+    assert!(!report.is_complete());
+    assert!(!report.is_total_failure());
+    assert!(report.is_stale(MarketPart::Tags));
+    assert!(!report.is_stale(MarketPart::Articles));
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(client.state().last_fetch, report);
+    assert_eq!(client.state().tags, market().tags);
+    assert_eq!(client.state().articles.len(), 2);
+
+    // Only when nothing comes back it's a total failure
+    handle.world().outages = MarketPart::ALL
+        .iter()
+        .skip(1)
+        .map(|part| part.endpoint().to_string())
+        .collect();
+    let report = client.fetch_market().await.unwrap();
+    assert_eq!(report.failures.len(), 4);
+    assert!(!report.is_total_failure());
+
+    handle.world().outages = MarketPart::ALL
+        .iter()
+        .map(|part| part.endpoint().to_string())
+        .collect();
+    let report = client.fetch_market().await.unwrap();
+    assert!(report.is_total_failure());
+    assert_eq!(client.state().articles.len(), 2);
+    assert_eq!(client.state().player.money, STARTING_MONEY);
+
+    handle.world().outages.clear();
+    let report = client.fetch_market().await.unwrap();
+    assert!(report.is_complete());
+    assert_eq!(client.state().tags.len(), 2);
+
+    handle.shutdown();
+}